@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+/// A list of logical CPUs, the format is same as Linux "cpulist", for example "0-7,16-23"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuList(pub Vec<usize>);
+
+impl std::str::FromStr for CpuList {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut out = vec![];
+        for part in s.trim().split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let range: Vec<&str> = part.splitn(2, '-').collect();
+            let parse = |n: &str| n.trim().parse::<usize>().map_err(|e| format!("invalid CPU number {:?}: {}", n, e));
+            let (first, last) = if range.len() == 2 {
+                (parse(range[0])?, parse(range[1])?)
+            } else {
+                let n = parse(range[0])?;
+                (n, n)
+            };
+            if first > last {
+                return Err(format!("invalid CPU range {:?}", part));
+            }
+            for n in first..=last {
+                if ! out.contains(&n) {
+                    out.push(n);
+                }
+            }
+        }
+        if out.is_empty() {
+            return Err("empty CPU list".to_string());
+        }
+        Ok(Self(out))
+    }
+}
+
+/// Where a logical CPU located
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuInfo {
+    pub id: usize, // logical CPU number
+    pub core: (usize, usize), // (package, core), the hyper-threads of a physical core are the same
+    pub node: usize, // NUMA node
+}
+
+/// Reads the CPU topology of this machine. (only Linux provides the physical core & NUMA node, otherwise every logical CPU as a core of node 0)
+pub fn topology() -> Vec<CpuInfo> {
+    let ids: Vec<usize> = match core_affinity::get_core_ids() {
+        Some(ids) => ids.into_iter().map(|c| c.id).collect(),
+        None => (0..num_cpus::get()).collect(),
+    };
+
+    #[cfg(target_os="linux")]
+    {
+        let read_num = |path: String| -> Option<usize> {
+            std::fs::read_to_string(path).ok()?.trim().parse().ok()
+        };
+
+        // cpu -> node
+        let mut nodes: std::collections::HashMap<usize, usize> = std::collections::HashMap::new();
+        if let Ok(dir) = std::fs::read_dir("/sys/devices/system/node") {
+            for entry in dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let node: usize = match name.strip_prefix("node").and_then(|n| n.parse().ok()) {
+                    Some(n) => n,
+                    None => { continue; }
+                };
+                if let Ok(list) = std::fs::read_to_string(entry.path().join("cpulist")) {
+                    if let Ok(CpuList(cpus)) = list.parse() {
+                        for cpu in cpus {
+                            nodes.insert(cpu, node);
+                        }
+                    }
+                }
+            }
+        }
+
+        return ids.into_iter().map(|id| {
+            let topo = format!("/sys/devices/system/cpu/cpu{}/topology", id);
+            let package = read_num(format!("{}/physical_package_id", topo)).unwrap_or(0);
+            let core = read_num(format!("{}/core_id", topo)).unwrap_or(id);
+            CpuInfo {
+                id,
+                core: (package, core),
+                node: nodes.get(&id).copied().unwrap_or(0),
+            }
+        }).collect();
+    }
+
+    #[allow(unreachable_code)]
+    ids.into_iter().map(|id| CpuInfo { id, core: (0, id), node: 0 }).collect()
+}
+
+/// Orders the allowed CPUs for mint threads: grouped by NUMA node, one logical CPU per physical core first, then the other hyper-threads.
+/// returns the CPU list (thread N should be pinned to the CPU of index N % len) and how many physical cores of them.
+pub fn plan(topo: &[CpuInfo], allowed: Option<&CpuList>) -> anyhow::Result<(Vec<usize>, usize)> {
+    let cpus: Vec<CpuInfo> = if let Some(CpuList(list)) = allowed {
+        let mut out = vec![];
+        for id in list {
+            match topo.iter().find(|c| c.id == *id) {
+                Some(c) => out.push(*c),
+                None => {
+                    return Err(anyhow::Error::msg(format!("CPU {} does not exist (or not allowed for this process)", id)));
+                }
+            }
+        }
+        out
+    } else {
+        topo.to_vec()
+    };
+
+    // node -> core -> the hyper-threads
+    let mut nodes: BTreeMap<usize, BTreeMap<(usize, usize), Vec<usize>>> = BTreeMap::new();
+    for c in &cpus {
+        nodes.entry(c.node).or_default().entry(c.core).or_default().push(c.id);
+    }
+
+    let mut first = vec![];
+    let mut siblings = vec![];
+    let mut cores = 0;
+    for (_, node_cores) in nodes {
+        let mut node_siblings = vec![];
+        for (_, mut ids) in node_cores {
+            ids.sort();
+            first.push(ids[0]);
+            node_siblings.extend_from_slice(&ids[1..]);
+            cores += 1;
+        }
+        siblings.push(node_siblings);
+    }
+    // the hyper-threads are used only if more threads than physical cores
+    let mut out = first;
+    for s in siblings {
+        out.extend(s);
+    }
+    Ok((out, cores))
+}
+
+/// Pins the current thread to a logical CPU.
+pub fn pin_current(cpu: usize) {
+    core_affinity::set_for_current(core_affinity::CoreId { id: cpu });
+}
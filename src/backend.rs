@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use themelio_nodeprot::ValClient;
+use themelio_structs::{
+    Address, BlockHeight,
+    CoinData, CoinDataHeight, CoinID,
+    CoinValue, Denom,
+    NetID, TxHash, TxKind,
+};
+use tmelcrypt::HashVal;
+
+use crate::repeat_fallible;
+use crate::state::WalletState;
+
+/// The mint wallet and the blockchain, as used by the seeds and the mint threads (so they can be tested with a fake chain).
+#[async_trait]
+pub trait Chain: Send + Sync + std::fmt::Debug {
+    /// the address of mint wallet
+    async fn address(&self) -> surf::Result<Address>;
+    /// whether the mint wallet is not on mainnet
+    async fn is_testnet(&self) -> surf::Result<bool>;
+    /// the MEL balance of mint wallet
+    async fn balance(&self) -> surf::Result<CoinValue>;
+    /// all unspent coins of mint wallet
+    async fn coins(&self) -> surf::Result<HashMap<CoinID, CoinData>>;
+    /// Signs and sends a tx, returns its hash and fee. it is refused (never sent) if the fee is higher than `max_fee`.
+    async fn send(
+        &self,
+        kind: TxKind,
+        inputs: Vec<CoinID>,
+        outputs: Vec<CoinData>,
+        data: Vec<u8>,
+        nobalance: Vec<Denom>,
+        max_fee: Option<CoinValue>,
+    ) -> surf::Result<(TxHash, CoinValue)>;
+    /// Signs a tx but never sends it, returns its fee.
+    async fn prepare_fee(
+        &self,
+        kind: TxKind,
+        inputs: Vec<CoinID>,
+        outputs: Vec<CoinData>,
+        data: Vec<u8>,
+        nobalance: Vec<Denom>,
+    ) -> surf::Result<CoinValue>;
+    /// waits a sent tx confirmed, returns the height of its block
+    async fn wait(&self, txhash: TxHash) -> surf::Result<u64>;
+    /// the current block height
+    async fn height(&self) -> surf::Result<u64>;
+    /// an unspent coin (None if spent)
+    async fn get_coin(&self, id: CoinID) -> surf::Result<Option<CoinDataHeight>>;
+    /// the header hash of a block
+    async fn header_hash(&self, height: BlockHeight) -> surf::Result<HashVal>;
+}
+
+/// the real chain: a melwalletd wallet and a full node
+#[derive(Clone, Debug)]
+pub struct NodeChain {
+    pub wallet: WalletState,
+    pub client: ValClient,
+}
+
+#[async_trait]
+impl Chain for NodeChain {
+    async fn address(&self) -> surf::Result<Address> {
+        Ok( self.wallet.0.summary().await?.address )
+    }
+
+    async fn is_testnet(&self) -> surf::Result<bool> {
+        Ok( self.wallet.0.summary().await?.network != NetID::Mainnet )
+    }
+
+    async fn balance(&self) -> surf::Result<CoinValue> {
+        self.wallet.get_balance().await
+    }
+
+    async fn coins(&self) -> surf::Result<HashMap<CoinID, CoinData>> {
+        Ok( self.wallet.0.get_coins().await?.into_iter().collect() )
+    }
+
+    async fn send(
+        &self,
+        kind: TxKind,
+        inputs: Vec<CoinID>,
+        outputs: Vec<CoinData>,
+        data: Vec<u8>,
+        nobalance: Vec<Denom>,
+        max_fee: Option<CoinValue>,
+    ) -> surf::Result<(TxHash, CoinValue)> {
+        // sent before the signing lock released: another tx must never be prepared from the same unspent coins
+        self.wallet.signing(async {
+            let tx = self.wallet.0.prepare_transaction(kind, inputs, outputs, vec![], data, nobalance).await?;
+
+            let fee = tx.fee;
+            if let Some(max) = max_fee {
+                if fee > max {
+                    return Err(surf::Error::new(403, anyhow::Error::msg(format!("refused to send any high-fee tx (fee {} MEL > {} MEL).", fee, max))));
+                }
+            }
+            Ok(( self.wallet.0.send_tx(tx).await?, fee ))
+        }).await?
+    }
+
+    async fn prepare_fee(
+        &self,
+        kind: TxKind,
+        inputs: Vec<CoinID>,
+        outputs: Vec<CoinData>,
+        data: Vec<u8>,
+        nobalance: Vec<Denom>,
+    ) -> surf::Result<CoinValue> {
+        let tx = self.wallet.signing(self.wallet.0.prepare_transaction(kind, inputs, outputs, vec![], data, nobalance)).await??;
+        Ok( tx.fee )
+    }
+
+    async fn wait(&self, txhash: TxHash) -> surf::Result<u64> {
+        Ok( self.wallet.0.wait_transaction(txhash).await? )
+    }
+
+    async fn height(&self) -> surf::Result<u64> {
+        Ok( self.client.snapshot().await?.current_header().height.0 )
+    }
+
+    async fn get_coin(&self, id: CoinID) -> surf::Result<Option<CoinDataHeight>> {
+        Ok( self.client.snapshot().await?.get_coin(id).await? )
+    }
+
+    async fn header_hash(&self, height: BlockHeight) -> surf::Result<HashVal> {
+        let snapshot = self.client.snapshot().await?;
+        Ok( repeat_fallible(|| snapshot.get_older(height)).await.current_header().hash() )
+    }
+}
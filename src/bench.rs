@@ -0,0 +1,166 @@
+use std::time::{Instant, SystemTime};
+
+use themelio_stf::Tip910MelPowHash;
+
+use crate::db::{BenchPoint, Calibration, RoundTiming, SpeedSource};
+
+// how many recent rounds are used for the correction
+const CORRECTION_ROUNDS: usize = 10;
+// the correction is limited to this (or 1/this), a larger error is likely a mistake
+const MAX_CORRECTION: f64 = 4.0;
+// the range of difficulty for --fixed-secs
+const MIN_DIFFICULTY: usize = 10;
+const MAX_DIFFICULTY: usize = 48;
+// a slower core gets a smaller difficulty by at most this
+const MAX_THREAD_REDUCE: usize = 3;
+
+/// the CPU model name of this machine (only readable on Linux, otherwise the architecture)
+pub fn cpu_model() -> String {
+    if let Ok(info) = std::fs::read_to_string("/proc/cpuinfo") {
+        for line in info.lines() {
+            if let Some((k, v)) = line.split_once(':') {
+                if k.trim() == "model name" {
+                    return v.trim().to_string();
+                }
+            }
+        }
+    }
+    std::env::consts::ARCH.to_string()
+}
+
+// hashes per second of each thread, by running melpow of `difficulty` on `threads` threads at the same time
+fn measure(difficulty: usize, threads: usize, cpus: Option<&[usize]>) -> f64 {
+    let handles: Vec<_> = (0..threads).map(|idx| {
+        let cpu = cpus.map(|v| v[idx % v.len()]);
+        std::thread::spawn(move || {
+            if let Some(cpu) = cpu {
+                crate::affinity::pin_current(cpu);
+            }
+            let chi: Vec<u8> = (0..32).map(|_| fastrand::u8(..)).collect();
+            let started = Instant::now();
+            melpow::Proof::generate(&chi, difficulty, Tip910MelPowHash);
+            2.0f64.powi(difficulty as _) / started.elapsed().as_secs_f64()
+        })
+    }).collect();
+
+    let speeds: Vec<f64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    speeds.iter().sum::<f64>() / speeds.len() as f64
+}
+
+/// Measures the melpow speed of single thread and all threads at each difficulty, prints the results.
+pub fn run(difficulties: &[usize], threads: usize, cpus: Option<&[usize]>) -> Calibration {
+    let mut difficulties = difficulties.to_vec();
+    difficulties.sort();
+    difficulties.dedup();
+
+    let model = cpu_model();
+    println!("benchmark of melpow: {} ({} threads)", model, threads);
+    println!("difficulty\tsingle-thread\tall-threads (each)\tall-threads (total)");
+
+    let mut points = vec![];
+    for difficulty in difficulties {
+        let single = measure(difficulty, 1, cpus);
+        let multi = measure(difficulty, threads, cpus);
+        println!("{}\t\t{:.2} kH/s\t{:.2} kH/s\t\t{:.2} kH/s",
+            difficulty, single / 1000.0, multi / 1000.0, multi * threads as f64 / 1000.0);
+        points.push(BenchPoint { difficulty, single, multi });
+    }
+
+    Calibration {
+        time: SystemTime::now(),
+        cpu_model: model,
+        threads,
+        points,
+    }
+}
+
+impl Calibration {
+    /// whether it was measured on this machine
+    pub fn matches(&self) -> bool {
+        self.cpu_model == cpu_model() && ! self.points.is_empty()
+    }
+
+    /// the expected hashes per second of each thread, if running `threads` threads of `difficulty`.
+    /// (interpolated between the measured points, and between single thread and all threads)
+    pub fn speed(&self, difficulty: usize, threads: usize) -> Option<f64> {
+        let at = |p: &BenchPoint| -> f64 {
+            if threads <= 1 || self.threads <= 1 {
+                p.single
+            } else if threads >= self.threads {
+                // more threads than measured: the total speed does not increase
+                p.multi * self.threads as f64 / threads as f64
+            } else {
+                p.single + (p.multi - p.single) * (threads - 1) as f64 / (self.threads - 1) as f64
+            }
+        };
+
+        let first = self.points.first()?;
+        let last = self.points.last()?;
+        if difficulty <= first.difficulty {
+            return Some(at(first));
+        }
+        if difficulty >= last.difficulty {
+            return Some(at(last));
+        }
+        for w in self.points.windows(2) {
+            let (a, b) = (&w[0], &w[1]);
+            if (a.difficulty..=b.difficulty).contains(&difficulty) {
+                let t = (difficulty - a.difficulty) as f64 / (b.difficulty - a.difficulty) as f64;
+                return Some(at(a) + (at(b) - at(a)) * t);
+            }
+        }
+        None
+    }
+}
+
+/// The correction factor of predicted round time (actual = predicted * factor) by the recent rounds of same thread count and same speed source,
+/// prefers the rounds of same difficulty if enough. returns the factor and how many rounds used (1.0 if none).
+pub fn correction(rounds: &[RoundTiming], difficulty: Option<usize>, threads: usize, source: SpeedSource) -> (f64, usize) {
+    let valid: Vec<&RoundTiming> = rounds.iter()
+        .filter(|r| r.threads == threads && r.source == source && r.predicted > 0.0 && r.actual > 0.0)
+        .collect();
+    let same: Vec<&RoundTiming> = valid.iter().copied()
+        .filter(|r| Some(r.difficulty) == difficulty)
+        .collect();
+    let used = if same.len() >= 3 { same } else { valid };
+    let used = &used[used.len().saturating_sub(CORRECTION_ROUNDS)..];
+    if used.is_empty() {
+        return (1.0, 0);
+    }
+
+    // geometric mean of the ratios, so a 2x slower round and a 2x faster round are even
+    let log_sum: f64 = used.iter().map(|r| (r.actual / r.predicted).ln()).sum();
+    let factor = (log_sum / used.len() as f64).exp();
+    (factor.clamp(1.0 / MAX_CORRECTION, MAX_CORRECTION), used.len())
+}
+
+/// The difficulty of which the round time is nearest to `secs` (in log scale, so 2x longer and 2x shorter are even),
+/// by the speed of each thread at a difficulty.
+pub fn solve_difficulty(secs: f64, speed_at: impl Fn(usize) -> f64) -> usize {
+    (MIN_DIFFICULTY..=MAX_DIFFICULTY)
+        .min_by(|a, b| {
+            let err = |d: usize| (2.0f64.powi(d as _) / speed_at(d) / secs).ln().abs();
+            err(*a).partial_cmp(&err(*b)).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap()
+}
+
+/// The difficulty of each thread by the measured speed of its core (None if unknown), so all proofs finish around the same time:
+/// a thread slower than the median gets a smaller difficulty (at most MAX_THREAD_REDUCE), never higher than `difficulty`.
+pub fn thread_difficulties(difficulty: usize, speeds: &[Option<f64>]) -> Vec<usize> {
+    let mut known: Vec<f64> = speeds.iter().flatten().copied().filter(|s| *s > 0.0).collect();
+    if known.is_empty() {
+        return vec![difficulty; speeds.len()];
+    }
+    known.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = known[known.len() / 2];
+
+    speeds.iter().map(|s| match s {
+        Some(s) if *s > 0.0 => {
+            // nearest in log scale (the hashes of melpow is a power of two)
+            let reduce = (-(s / median).log2().round()).max(0.0) as usize;
+            difficulty - reduce.min(MAX_THREAD_REDUCE).min(difficulty)
+        },
+        _ => difficulty,
+    }).collect()
+}
@@ -1,7 +1,12 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use structopt::StructOpt;
 use themelio_structs::{ Address, NetID };
+
+use crate::state::ExpiredSeeds;
+use crate::affinity::CpuList;
+use crate::schedule::Schedule;
 // use tmelcrypt::Ed25519SK;
 
 #[derive(Debug, StructOpt, Clone)]
@@ -34,7 +39,26 @@ pub struct CmdOpts {
 
     #[structopt(long)]
     /// Force a certain number of threads. Defaults to the number of *physical* CPUs.
+    /// (more than 255 threads uses multiple seed txs per round)
     pub threads: Option<usize>,
+    #[structopt(long)]
+    /// Pin each mint thread to a distinct physical core (grouped by NUMA node), the hyper-threads are used only if more threads than cores.
+    pub pin_threads: bool,
+    #[structopt(long)]
+    /// Only use these logical CPUs for minting, for example "0-7,16-23" (implies --pin-threads, and defaults to one thread per physical core of them)
+    pub cpus: Option<CpuList>,
+
+    #[structopt(long)]
+    /// Only minting inside these time windows (local time), separated by ";", for example "Mon-Fri 18:00-08:00; Sat-Sun 00:00-24:00".
+    /// a round will use a smaller difficulty to finish inside the window (if not --fixed-diff), otherwise the mint threads are paused at the end of window and resumed in next window.
+    pub schedule: Option<Schedule>,
+
+    #[structopt(long)]
+    /// Reduce the active mint threads if the load average (1 minute, excludes our mint threads) of other programs is higher than this. (Linux only)
+    pub max_load: Option<f64>,
+    #[structopt(long)]
+    /// Reduce the active mint threads (or pause all) if the CPU temperature (Celsius) is higher than this. (Linux only)
+    pub max_temp: Option<f64>,
 
     #[structopt(long)]
     /// Whether melminter should be skipping the check that require balance >= 0.05 MEL
@@ -58,6 +82,22 @@ pub struct CmdOpts {
     /// Whether enable debug output for all mods
     pub debug: bool,
 
+    #[structopt(long)]
+    /// Read the password of mint wallet from this file (otherwise from env MELMINTER_WALLET_PASSWORD if set).
+    /// new mint wallet will be created encrypted with it, and locked again after each signing.
+    pub wallet_password_file: Option<PathBuf>,
+    #[structopt(long)]
+    /// Ask the password of mint wallet interactively (if without --wallet-password-file and env MELMINTER_WALLET_PASSWORD)
+    pub ask_wallet_password: bool,
+
+    #[structopt(long)]
+    /// Import an existing secret key (hex, exported by '--export-sk' or melwallet-cli) as the mint wallet, for migrating a minter to new hardware.
+    /// (NOTE: the command line may be visible to other users, prefer --import-sk-file)
+    pub import_sk: Option<String>,
+    #[structopt(long)]
+    /// Same as --import-sk, but read the secret key from this file.
+    pub import_sk_file: Option<PathBuf>,
+
     #[structopt(long)]
     /// Whether exporting the secret key of mint wallet. (defaults to do nothing)
     /// maybe only useful for without payout option
@@ -70,16 +110,87 @@ pub struct CmdOpts {
     ///   because the ERG you minted may not be enough to cover the network fee for doscMint transactions)
     pub fixed_diff: Option<u8>,
     #[structopt(long)]
-    /// if provided, to control the approx time to specified seconds.
+    /// if provided, to control the approx time to specified seconds. (the difficulty of nearest round time is used, it is refused if unprofitable)
     /// [fixed-secs and fixed-diff is cannot give both!]
     /// PLEASE NOTE SEE --fixed-diff
     pub fixed_secs: Option<u32>,
 
     #[structopt(long)]
-    /// [EXPERIMENTAL] Whether melminter should be bulk to sent new-coin seeds tx...
+    /// Whether melminter should be bulk to sent new-coin seeds tx (a single coin for all threads, it will be split before minting, and any leftover is used by later rounds)
     pub bulk_seeds: bool,
 
+    #[structopt(long)]
+    /// Fixed TTL (unit: blocks) of new-coin seeds, older seeds will be expired.
+    /// otherwise computed for each round by the reward decay, that the reward of proof still covers the DoscMint fee.
+    pub seed_ttl: Option<u64>,
+
+    #[structopt(long, default_value = "keep")]
+    /// What to do with the expired new-coin seeds: "keep" them in mint wallet, "consolidate" them back to mint wallet (fewer coins), or "burn" them to a null address.
+    pub expired_seeds: ExpiredSeeds,
+    #[structopt(long)]
+    /// Only display what expired seeds would be swept (by --expired-seeds), never send them.
+    pub sweep_dry_run: bool,
+
+    #[structopt(long)]
+    /// Directory of the local database file, otherwise defaults to the OS config directory (for example ~/.config/melminter-mod/)
+    pub data_dir: Option<PathBuf>,
+    #[structopt(long)]
+    /// Full path of the local database file. [data-dir and db-path is cannot give both!]
+    pub db_path: Option<PathBuf>,
+
+    #[structopt(long, default_value = "7")]
+    /// How many days to keep the records of confirmed/failed proofs in the local database, older records will be pruned.
+    pub proof_retention_days: u64,
+
     // #[structopt(long)]
     // /// Drain the fee reserve at the start.
     // pub drain_reserve: bool,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub enum Command {
+    /// Maintenance of the local database (does not start minting)
+    Db(DbCmd),
+    /// Measure the melpow speed of single thread and all threads (uses --threads, --pin-threads, --cpus), the calibration is stored in the local database for predict the round time
+    Bench {
+        #[structopt(long, use_delimiter = true, default_value = "14,17,20")]
+        /// the difficulties to measure
+        difficulties: Vec<usize>,
+        #[structopt(long)]
+        /// only print the results, does not store the calibration
+        no_save: bool,
+    },
+}
+
+#[derive(Debug, StructOpt, Clone)]
+/// (the tables are separated by network & wallet: use "--network" for select one, otherwise the shared tables of older versions)
+pub enum DbCmd {
+    /// List all tables (of all networks & wallets) and the key count of each table
+    Tables,
+    /// Dump a table as JSON to stdout
+    Dump {
+        /// the table name (see "db tables")
+        table: String,
+    },
+    /// Export the pending proofs to a JSON file, for move them to another machine (it must use the same mint wallet). they are marked as exported here, so never submitted by both
+    ExportProofs {
+        file: PathBuf,
+    },
+    /// Import proofs from a JSON file (exported by "db export-proofs"), any existing proofs will be skipped
+    ImportProofs {
+        file: PathBuf,
+    },
+    /// Verify the key list of metadata matches the actual contents of each table (of all networks & wallets)
+    Verify {
+        #[structopt(long)]
+        /// Remove the listed keys that do not have a value
+        repair: bool,
+    },
+    /// Compact the database file by rewrite it to a new file (the old file kept as a backup)
+    Compact,
+    /// Encrypt the database by a new passphrase (or change the passphrase, or disable encryption by an empty one)
+    Rekey,
 }
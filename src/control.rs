@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The panic payload of a cancelled mint thread (melpow cannot be stopped by other ways), caught by the thread itself.
+#[derive(Debug)]
+pub struct Cancelled;
+
+/// Ignores the panic message of Cancelled, other panics are reported as usual.
+pub fn install_panic_hook() {
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if info.payload().is::<Cancelled>() {
+            return;
+        }
+        prev(info);
+    }));
+}
+
+/// Shared control of the mint threads, checked by their progress callback of melpow.
+/// (melpow cannot be checkpointed to disk, so a paused thread just blocks in the callback and keeps its state in memory)
+#[derive(Debug)]
+pub struct MintControl {
+    paused: AtomicBool, // pause all threads (by the schedule)
+    active: AtomicUsize, // only the threads of index < active are running (by the governor)
+    cancelled: AtomicBool, // stop all threads of current round
+    stopped: AtomicBool, // the minter is stopping: stop all threads of any round (never reset)
+    cancelled_threads: Mutex<HashSet<usize>>, // stop some threads (for example the seed spent)
+    // since when throttled (paused, or not all threads active), and the total throttled time (for honest ETA)
+    throttled_since: Mutex<Option<Instant>>,
+    throttled_total: Mutex<Duration>,
+}
+
+impl Default for MintControl {
+    fn default() -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            active: AtomicUsize::new(usize::MAX),
+            cancelled: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            cancelled_threads: Mutex::new(HashSet::new()),
+            throttled_since: Mutex::new(None),
+            throttled_total: Mutex::new(Duration::ZERO),
+        }
+    }
+}
+
+impl MintControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        self.update_throttled();
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.update_throttled();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// limits how many threads are running, usize::MAX for all.
+    pub fn set_active(&self, active: usize) {
+        self.active.store(active, Ordering::SeqCst);
+        self.update_throttled();
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// stops all threads of current round, the finished proofs are kept.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.is_stopped()
+    }
+
+    /// stops all threads of current and later rounds (a stop request, so it is kept by reset)
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// stops a thread of current round.
+    pub fn cancel_thread(&self, idx: usize) {
+        self.cancelled_threads.lock().unwrap().insert(idx);
+    }
+
+    pub fn is_thread_cancelled(&self, idx: usize) -> bool {
+        self.is_cancelled() || self.cancelled_threads.lock().unwrap().contains(&idx)
+    }
+
+    /// reset for a new round (all threads running, unless stopped)
+    pub fn reset(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.active.store(usize::MAX, Ordering::SeqCst);
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.cancelled_threads.lock().unwrap().clear();
+        *self.throttled_since.lock().unwrap() = None;
+        *self.throttled_total.lock().unwrap() = Duration::ZERO;
+    }
+
+    // starts or stops the clock of throttled time
+    fn update_throttled(&self) {
+        let throttled = self.is_paused() || self.active() != usize::MAX;
+        let mut since = self.throttled_since.lock().unwrap();
+        if throttled && since.is_none() {
+            *since = Some(Instant::now());
+        } else if (!throttled) && since.is_some() {
+            *self.throttled_total.lock().unwrap() += since.take().unwrap().elapsed();
+        }
+    }
+
+    /// total throttled time of this round (including the current)
+    pub fn throttled_time(&self) -> Duration {
+        let current = self.throttled_since.lock().unwrap().map(|s| s.elapsed()).unwrap_or_default();
+        *self.throttled_total.lock().unwrap() + current
+    }
+
+    /// called by the mint thread of index `idx`: blocks while paused (or throttled), unwinds by Cancelled if cancelled.
+    pub fn checkpoint(&self, idx: usize) {
+        loop {
+            if self.is_thread_cancelled(idx) {
+                std::panic::panic_any(Cancelled);
+            }
+            if !(self.is_paused() || idx >= self.active()) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// runs the melpow of a mint thread, returns None if cancelled.
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> Option<T> {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+            Ok(v) => Some(v),
+            Err(e) if e.is::<Cancelled>() => None,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+}
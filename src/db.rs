@@ -1,14 +1,20 @@
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
 use std::collections::{HashSet, HashMap};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::fs::{File, OpenOptions};
 
 use boringdb;
 use dirs;
 use anyhow::Context;
+use fs2::FileExt;
 
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
-use themelio_structs::{CoinID, CoinDataHeight};
+use bincode::Options;
+use tmelcrypt::HashVal;
+use themelio_structs::{Address, CoinID, CoinDataHeight, CoinValue, NetID, TxHash};
+
+use crate::dbcrypt::{self, Cipher, CipherInfo};
 
 // filename of database, all db.rs logic need store to one file, does not create others unless major changes to format/function/goals (then these need split to a new .rs file)
 pub const DB_FILENAME: &str = "melmintdb_sqlite3";
@@ -26,6 +32,33 @@ pub const TABLE_NEWCOINS:   &str = "new_coin_txs";
 pub const TABLE_SWAPS:      &str = "erg2mel_swaps";
 pub const TABLE_BALANCES:   &str = "balance_history";
 
+// all tables that store data (except metadata), the migrations will be applied to them.
+pub const TABLES: &[&str] = &[TABLE_PROOF_LIST, TABLE_LOGS, TABLE_NEWCOINS, TABLE_SWAPS, TABLE_BALANCES];
+
+// current version of the database schema, please increase it for any incompatible changes to data formats, and add a migration to MIGRATIONS.
+// (version 0 means a database created before schema versioning)
+pub const SCHEMA_VERSION: u32 = 2;
+
+// key of the schema version in TABLE_METADATA
+const MD_SCHEMA: &[u8] = b"schema";
+// key of the list of all namespaces in TABLE_METADATA
+const MD_NAMESPACES: &[u8] = b"namespaces";
+// key of the encryption parameters in TABLE_METADATA (not exists if the database is not encrypted)
+const MD_CIPHER: &[u8] = b"cipher";
+// key of the benchmark calibration in TABLE_METADATA (shared by all networks & wallets, it is about the machine)
+const MD_CALIBRATION: &[u8] = b"calibration";
+// key of the recent round timings in TABLE_METADATA (also about the machine)
+const MD_ROUNDS: &[u8] = b"rounds";
+// how many recent round timings are kept
+const MAX_ROUNDS: usize = 200;
+
+// the database file specified by user (--db-path or --data-dir), otherwise uses the default location.
+static DB_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+// the namespace of tables (network & wallet name) for this process, None for the shared tables (used by older versions).
+static NAMESPACE: RwLock<Option<String>> = RwLock::new(None);
+// the key of encrypted database (unlocked by passphrase), None if not encrypted.
+static CIPHER: RwLock<Option<Cipher>> = RwLock::new(None);
+
 /* All data formats:
  * No Any functions about to format/serde/generating-value.
  * just struct only. */
@@ -41,6 +74,10 @@ pub enum MetadataKind {
     Log(LogRecord),
     Nothing,
     KeyList(HashSet<Vec<u8>>),
+    Schema(u32), // the schema version of the whole database
+    Cipher(CipherInfo), // the key derivation parameters of encrypted database
+    Calibration(Calibration), // the melpow benchmark of this machine (see "bench" subcommand)
+    Rounds(Vec<RoundTiming>), // the predicted and actual time of recent rounds
     // come soon...
 }
 
@@ -87,18 +124,129 @@ pub struct TrySendProof {
     pub coin: CoinID,
     pub data: CoinDataHeight,
     pub proof: Vec<u8>,
+
+    // how this proof generated
+    pub difficulty: usize, // the difficulty of this proof, must submit with this value. (zero means unknown: migrated from older version and needs recover)
+    pub chi: HashVal, // the puzzle of melpow, keyed hash of seed by the header hash of seed located
+    pub thread: usize, // index of mint thread that generated this proof
+    pub elapsed: Duration, // how long it takes to generate
+}
+// every value stored by Map is wrapped by this. (keys are not)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32, // the schema version of body
+    pub encrypted: bool, // is the body encrypted by the passphrase of database
+    pub body: Vec<u8>, // bincode encoded value (or encrypted)
+}
+// older layout of Envelope (schema version 1), only used for migration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnvelopeV1 {
+    pub version: u32,
+    pub body: Vec<u8>,
+}
+
+// older layout of TrySendProof (v0.8.12 and before), only used for migration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrySendProofV0 {
+    pub coin: CoinID,
+    pub data: CoinDataHeight,
+    pub proof: Vec<u8>,
 }
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct TrySendProofState {
     pub fails: u8, // total failed count, only add ops (no any other)
     pub created: SystemTime, // tx prepare time (but not sent)
-    pub sent: bool, // is this tx send-ed?
-    pub failed: bool, // is this tx complete-failed? other files specify a max retry number and no longer try-again if limit-reach.
+    pub updated: SystemTime, // last time of .status changed, the retention of old records is counted from here
+    pub status: ProofStatus, // where this proof is in the lifecycle (pending -> submitted -> confirmed, or failed)
     pub errors: Vec<String>, // a list of possible error(s), any error about to this tx should saves here (do not logging to other tables)
 }
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum ProofStatus {
+    Pending, // not sent yet (or waiting to retry), only these will be re-loaded to the submit queue
+    Submitted(TxHash), // DoscMint tx sent to melwalletd, the value is the tx hash
+    Confirmed(u64), // the DoscMint tx is confirmed at this block height
+    Failed(String), // complete-failed, no longer try-again (reach the max retry, or any other reason given here)
+    Quarantined(String), // the proof cannot pass local verification (corrupt or mismatched chi), never submit it
+    Exported(String), // moved to another machine by "db export-proofs" (the value is the export file), never submit it here
+}
+// the lifecycle of a new-coin seed tx (stored in TABLE_NEWCOINS with key "seed.{tx hash}")
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeedRecord {
+    pub txhash: TxHash, // the new-coin tx (also the custom denom of its seeds)
+    pub height: u64, // block height of the tx confirmed, for the TTL check (no need to query it again)
+    pub threads: usize, // how many threads the seeds for (0 if unknown: created by older versions)
+    pub bulk: bool, // a single coin with value of threads, or one coin per thread
+    pub ttl: Option<u64>, // the TTL (blocks) when created
+    pub state: SeedState,
+    pub created: SystemTime,
+    pub updated: SystemTime, // last time of .state changed
+}
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SeedState {
+    Fresh, // confirmed, not used yet
+    InUse, // minting by the seeds
+    Proved, // the proofs of seeds generated
+    Expired, // older than TTL, the seeds will never be used
+    Swept(TxHash), // the expired seeds are consolidated or burned by this tx
+    Split(TxHash), // the bulk seed is split to unit seeds by this tx (the outputs of it are the seeds)
+}
+// a sweep of expired new-coin seeds (stored in TABLE_NEWCOINS with key "sweep.{tx hash}")
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeedSweep {
+    pub time: SystemTime, // when the sweep tx sent
+    pub txhash: TxHash, // the sweep tx
+    pub dst: Address, // where the expired seeds sent to (our own wallet, or a null address if burned)
+    pub burned: bool, // true if sent to a null address
+    pub seeds: Vec<(TxHash, CoinValue)>, // which seed tx (the denom) and how many values of it swept
+    pub fee: CoinValue, // fee of the sweep tx (it also includes the outputs of new seeds)
+}
+// the melpow speed of this machine, measured by "bench" subcommand
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Calibration {
+    pub time: SystemTime, // when measured
+    pub cpu_model: String, // the calibration is ignored if the CPU changed
+    pub threads: usize, // how many threads of the all-thread measurement
+    pub points: Vec<BenchPoint>, // ordered by difficulty
+}
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BenchPoint {
+    pub difficulty: usize,
+    pub single: f64, // hashes per second of a single thread (others idle)
+    pub multi: f64, // hashes per second of each thread (all threads running)
+}
+// the time of a finished round, for correct the prediction of later rounds
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RoundTiming {
+    pub time: SystemTime, // when finished
+    pub difficulty: usize,
+    pub threads: usize,
+    pub predicted: f64, // seconds, predicted by the speed only (before correction)
+    pub actual: f64, // seconds, excludes the throttled time
+    pub source: SpeedSource, // where the predicted speed came from (each source has its own correction)
+}
+// the speed of a round time prediction
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SpeedSource {
+    Live, // the real speed of finished rounds
+    Calibration, // the stored calibration (see "bench" subcommand)
+    Benchmark, // a short benchmark at the start
+}
+// older layout of TrySendProofState (v0.8.12 and before), only used for migration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrySendProofStateV0 {
+    pub fails: u8,
+    pub created: SystemTime,
+    pub sent: bool,
+    pub failed: bool,
+    pub errors: Vec<String>,
+}
 
 // Get (read-only) the database file should be located path. (no any writes)
 pub fn db_path() -> anyhow::Result< Box<Path> > {
+    if let Some(path) = DB_PATH.read().unwrap().clone() {
+        return Ok( path.into_boxed_path() );
+    }
+
     if let Some(mut dir) = confdir() {
         dir.push(DB_FILENAME);
         return Ok( dir.into_boxed_path() );
@@ -119,9 +267,14 @@ pub fn confdir() -> Option<PathBuf> {
     }
 }
 
+// Overrides the location of database file, must be called before any access.
+pub fn set_db_path(path: PathBuf) {
+    *DB_PATH.write().unwrap() = Some(path);
+}
+
 // Opens the database with fixed location, will creates file/dir if not found.
 pub fn db_open() -> anyhow::Result<boringdb::Database> {
-    if let Some(dir) = confdir() {
+    if let Some(dir) = db_path()?.parent() {
         std::fs::DirBuilder::new()
             .recursive(true)
             .create(dir)?;
@@ -136,6 +289,676 @@ pub fn dict_open(name: &str) -> anyhow::Result<boringdb::Dict> {
     Ok( db.open_dict(name)? )
 }
 
+// Sets the namespace of tables for this process by network & wallet name (and registers it to metadata), must be called before any Map used.
+// so the proofs/logs of different networks or wallets will never be mixed.
+pub fn set_namespace(netid: NetID, wallet: &str) -> anyhow::Result<String> {
+    // only [0-9a-zA-Z_] is allowed in table names
+    let ns: String = format!("{}__{}", netid, wallet).chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let dict = dict_open(TABLE_METADATA)?;
+    let mut all = namespace_list(&dict)?;
+    if all.insert(ns.as_bytes().to_vec()) {
+        let mdata = Metadata {
+            table: String::new(),
+            kind: MetadataKind::KeyList(all),
+            info: String::new(),
+        };
+        dict.insert(MD_NAMESPACES.to_vec(), bincode::serialize(&mdata)?)?;
+        dict.flush()?;
+    }
+
+    *NAMESPACE.write().unwrap() = Some(ns.clone());
+    Ok(ns)
+}
+
+// Get the namespace of this process
+pub fn namespace() -> Option<String> {
+    NAMESPACE.read().unwrap().clone()
+}
+
+// Get all namespaces in database, the first one is always None (the shared tables)
+pub fn namespaces() -> anyhow::Result<Vec<Option<String>>> {
+    let mut out = vec![None];
+    let mut list: Vec<Vec<u8>> = namespace_list(&dict_open(TABLE_METADATA)?)?.into_iter().collect();
+    list.sort();
+    for ns in list {
+        out.push(Some( String::from_utf8(ns)? ));
+    }
+    Ok(out)
+}
+
+fn namespace_list(md: &boringdb::Dict) -> anyhow::Result<HashSet<Vec<u8>>> {
+    if let Some(raw) = md.get(MD_NAMESPACES)? {
+        let mdata: Metadata = bincode::deserialize(&raw)?;
+        match mdata.kind {
+            MetadataKind::KeyList(kl) => Ok(kl),
+            _ => Err(anyhow::Error::msg("metadata type not equal KeyList")),
+        }
+    } else {
+        Ok(HashSet::new())
+    }
+}
+
+// the real name of a table in namespace
+pub fn table_name(table: &str, ns: Option<&str>) -> String {
+    if let Some(ns) = ns {
+        format!("{}__{}", table, ns)
+    } else {
+        table.to_string()
+    }
+}
+
+/// an advisory lock of the tables in a namespace, released if dropped or process exited.
+pub struct DbLock {
+    _file: File,
+    pub path: PathBuf,
+}
+
+// Locks the tables of a namespace ("{db_path}.{namespace}.lock"), returns error if another process holds it.
+pub fn db_lock(ns: Option<&str>) -> anyhow::Result<DbLock> {
+    let path = PathBuf::from(format!("{}.{}.lock", db_path()?.display(), ns.unwrap_or("shared")));
+    let file = OpenOptions::new().create(true).write(true).open(&path)?;
+    file.try_lock_exclusive().with_context(|| format!("the database tables of {:?} are being used by another process (lock file {:?})", ns, path))?;
+
+    Ok(DbLock { _file: file, path })
+}
+
+// Appends a log record to TABLE_LOGS. (uses a new mapping, so any current dict of caller's Map will not be changed)
+pub fn write_log(id: &str, kind: WhatLog, event: &str, text: &str, msg: Vec<u8>) -> anyhow::Result<()> {
+    let rec = LogRecord {
+        id: id.to_string(),
+        kind,
+        time: SystemTime::now(),
+        backtrace: None,
+        event: event.to_string(),
+        text: text.to_string(),
+        msg,
+    };
+
+    // key format: "log.{unix time nanos}.{random}" for keep the order and avoid conflict
+    let key = format!("log.{}.{}", rec.time.duration_since(UNIX_EPOCH)?.as_nanos(), fastrand::u32(..));
+
+    let mut map = Map::new();
+    map.dict(TABLE_LOGS)?;
+    map.set(key, rec)?;
+    map.flush()
+}
+
+// Records a sweep of expired seeds to TABLE_NEWCOINS. (uses a new mapping, like write_log)
+pub fn write_sweep(rec: &SeedSweep) -> anyhow::Result<()> {
+    let mut map = Map::new();
+    map.dict(TABLE_NEWCOINS)?;
+    map.set(format!("sweep.{}", rec.txhash), rec)?;
+    map.flush()
+}
+
+// Writes (or replaces) a proof record to TABLE_PROOF_LIST. (uses a new mapping, for the proofs streamed while minting)
+pub fn write_proof(trys: &TrySendProof, tryst: &TrySendProofState) -> anyhow::Result<()> {
+    let mut map = Map::new();
+    map.dict(TABLE_PROOF_LIST)?;
+    map.set(trys, tryst)?;
+    map.flush()
+}
+
+// Writes (or replaces) a seed record to TABLE_NEWCOINS.
+pub fn write_seed(rec: &SeedRecord) -> anyhow::Result<()> {
+    let mut map = Map::new();
+    map.dict(TABLE_NEWCOINS)?;
+    map.set(format!("seed.{}", rec.txhash), rec)?;
+    map.flush()
+}
+
+// Changes the state of a seed record, returns false if it is not recorded.
+pub fn set_seed_state(txhash: TxHash, state: SeedState) -> anyhow::Result<bool> {
+    let mut map = Map::new();
+    map.dict(TABLE_NEWCOINS)?;
+    let key = format!("seed.{}", txhash);
+    let mut rec: SeedRecord = match map.get(&key)? {
+        Some(rec) => *rec,
+        None => { return Ok(false); }
+    };
+    if rec.state != state {
+        rec.state = state;
+        rec.updated = SystemTime::now();
+        map.set(&key, rec)?;
+        map.flush()?;
+    }
+    Ok(true)
+}
+
+// Reads all seed records of TABLE_NEWCOINS, any un-readable records will be skipped.
+pub fn load_seeds() -> anyhow::Result<HashMap<TxHash, SeedRecord>> {
+    let mut map = Map::new();
+    map.dict(TABLE_NEWCOINS)?;
+
+    let mut out = HashMap::new();
+    for key in map.cur().keys()? {
+        // the sweep records are also stored here
+        let key: String = match bincode::deserialize(&key) {
+            Ok(k) => k,
+            Err(_) => { continue; }
+        };
+        if ! key.starts_with("seed.") {
+            continue;
+        }
+
+        match map.get::<_, SeedRecord>(&key) {
+            Ok(Some(rec)) => {
+                out.insert(rec.txhash, *rec);
+            },
+            Ok(None) => {},
+            Err(e) => {
+                log::warn!("skip an un-readable seed record {:?}: {:?}", key, e);
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Removes the proved/swept/split seed records older than retention, returns how many removed.
+pub fn prune_seeds(retention: Duration) -> anyhow::Result<usize> {
+    let mut map = Map::new();
+    map.dict(TABLE_NEWCOINS)?;
+
+    let records = load_seeds()?;
+    let mut pruned = 0;
+    for (txhash, rec) in records.iter() {
+        match &rec.state {
+            SeedState::Proved | SeedState::Swept(_) => {},
+            // the split seeds are recognized by this record, keep it until they finished
+            SeedState::Split(child) => {
+                if let Some(c) = records.get(child) {
+                    match c.state {
+                        SeedState::Proved | SeedState::Swept(_) => {},
+                        _ => { continue; }
+                    }
+                }
+            },
+            _ => { continue; }
+        }
+        let age = rec.updated.elapsed().unwrap_or_default();
+        if age > retention && map.remove(format!("seed.{}", txhash))? {
+            pruned += 1;
+        }
+    }
+    map.flush()?;
+    Ok(pruned)
+}
+
+// Reads all proof records of TABLE_PROOF_LIST (the current dict of map), any un-readable records will be skipped.
+pub fn load_proofs(map: &Map) -> anyhow::Result<Vec<(TrySendProof, TrySendProofState)>> {
+    let mut out = vec![];
+    for key in map.cur().keys()? {
+        let trys: TrySendProof = match bincode::deserialize(&key) {
+            Ok(k) => k,
+            Err(e) => {
+                log::warn!("skip an un-readable key of TABLE_PROOF_LIST: {:?}", e);
+                continue;
+            }
+        };
+        match map.get(&trys) {
+            Ok(Some(tryst)) => {
+                out.push((trys, *tryst));
+            },
+            Ok(None) => {
+                log::warn!("missing hit a key {:?} of TABLE_PROOF_LIST: unexpected none value! CHECK METADATA LOGIC", (trys.coin, trys.data));
+            },
+            Err(e) => {
+                log::warn!("skip an un-readable proof state {:?}: {:?}", (trys.coin, trys.data), e);
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Reads the schema version of database. (0 if not stored)
+pub fn schema_version() -> anyhow::Result<u32> {
+    let dict = dict_open(TABLE_METADATA)?;
+    if let Some(raw) = dict.get(MD_SCHEMA)? {
+        let mdata: Metadata = bincode::deserialize(&raw)?;
+        match mdata.kind {
+            MetadataKind::Schema(v) => Ok(v),
+            _ => Err(anyhow::Error::msg("metadata type not equal Schema")),
+        }
+    } else {
+        Ok(0)
+    }
+}
+
+fn set_schema_version(version: u32) -> anyhow::Result<()> {
+    let dict = dict_open(TABLE_METADATA)?;
+    let mdata = Metadata {
+        table: String::new(),
+        kind: MetadataKind::Schema(version),
+        info: format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+    };
+    dict.insert(MD_SCHEMA.to_vec(), bincode::serialize(&mdata)?)?;
+    dict.flush()?;
+    Ok(())
+}
+
+// forward migrations, MIGRATIONS[n] upgrades a database from version n to n+1.
+const MIGRATIONS: &[fn() -> anyhow::Result<()>] = &[migrate_v0_v1, migrate_v1_v2];
+
+// Makes sure the database schema is current, must be called at startup before any access.
+// a database newer than this program will be refused; an older one will be backup (copy to "{db_path}.v{version}.{unix-time}.bak") and then migrated.
+pub fn db_init() -> anyhow::Result<()> {
+    let path = db_path()?;
+    let existed = path.exists();
+
+    let version = schema_version()?;
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::Error::msg(format!("the database {:?} is schema version {}, but this program only supports <= {}. please upgrade {}.", path, version, SCHEMA_VERSION, env!("CARGO_PKG_NAME"))));
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // just created, nothing to migrate.
+    if ! existed {
+        return set_schema_version(SCHEMA_VERSION);
+    }
+
+    let backup = db_backup(&format!("v{}", version))?;
+    log::warn!("database schema upgrading from version {} to {}, the backup is saved to {:?}", version, SCHEMA_VERSION, backup);
+
+    for v in version .. SCHEMA_VERSION {
+        log::info!("migrating database from version {} to {}...", v, v+1);
+        MIGRATIONS[v as usize]()?;
+        set_schema_version(v+1)?;
+    }
+    Ok(())
+}
+
+// Copy the database file (and the sqlite3 journal files if exists) to a backup, returns the backup path.
+pub fn db_backup(tag: &str) -> anyhow::Result<PathBuf> {
+    let path = db_path()?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let backup = PathBuf::from(format!("{}.{}.{}.bak", path.display(), tag, time));
+
+    std::fs::copy(&path, &backup).context("cannot backup database")?;
+    for suffix in ["-wal", "-shm"] {
+        let journal = PathBuf::from(format!("{}{}", path.display(), suffix));
+        if journal.exists() {
+            std::fs::copy(&journal, format!("{}{}", backup.display(), suffix)).context("cannot backup database journal")?;
+        }
+    }
+    Ok(backup)
+}
+
+// Rewrites the metadata and all tables (listed keys only) to a new database file, and replaces the current one. returns the sizes (before, after).
+// the old file will be kept as a backup, please make sure no any other process is using this database.
+pub fn db_compact() -> anyhow::Result<(u64, u64)> {
+    let path = db_path()?;
+    let before = std::fs::metadata(&path)?.len();
+
+    let tmp = PathBuf::from(format!("{}.compact", path.display()));
+    let cipher = dict_open(TABLE_METADATA)?.get(MD_CIPHER)?.map(|v| v.to_vec());
+    db_rewrite(&tmp, cipher, |v| Ok(v.to_vec()))?;
+
+    let backup = db_backup("compact")?;
+    log::info!("database backup before compact: {:?}", backup);
+    db_replace(&tmp)?;
+
+    let after = std::fs::metadata(&path)?.len();
+    Ok((before, after))
+}
+
+// Writes the metadata and all tables (listed keys only) to a new database file `tmp`, each value is converted by `convert`. returns how many values written.
+// the encryption parameters (MD_CIPHER) of new file is `cipher` (the raw metadata value, None for not encrypted).
+fn db_rewrite(tmp: &Path, cipher: Option<Vec<u8>>, convert: impl Fn(&[u8]) -> anyhow::Result<Vec<u8>>) -> anyhow::Result<usize> {
+    // a leftover of interrupted rewrite
+    if tmp.exists() {
+        std::fs::remove_file(tmp)?;
+    }
+    let new_db = boringdb::Database::open(tmp)?;
+
+    let md = new_db.open_dict(TABLE_METADATA)?;
+    let old_md = dict_open(TABLE_METADATA)?;
+    for key in [MD_SCHEMA, MD_NAMESPACES, MD_CALIBRATION, MD_ROUNDS] {
+        if let Some(v) = old_md.get(key)? {
+            md.insert(key.to_vec(), v.to_vec())?;
+        }
+    }
+    if let Some(v) = cipher {
+        md.insert(MD_CIPHER.to_vec(), v)?;
+    }
+    md.flush()?;
+
+    let mut count = 0;
+    for ns in namespaces()? {
+        for table in TABLES {
+            let dm = DictMap::open_ns(table, ns.as_deref())?;
+            let old = dm.dict.as_ref().unwrap().as_ref();
+            let new = new_db.open_dict(&table_name(table, ns.as_deref()))?;
+            for key in dm.keys()? {
+                if let Some(v) = old.get(&key)? {
+                    new.insert(key, convert(&v)?)?;
+                    count += 1;
+                }
+            }
+            if let Some(v) = old.get(&dm.md_keylist)? {
+                new.insert(dm.md_keylist.clone(), v.to_vec())?;
+            }
+            new.flush()?;
+        }
+    }
+    Ok(count)
+}
+
+// Replaces the database file by a new one written by db_rewrite. (rename is atomic, so the database is either old or new one if interrupted)
+fn db_replace(tmp: &Path) -> anyhow::Result<()> {
+    let path = db_path()?;
+    std::fs::rename(tmp, &path)?;
+    // the journal files belong to the old file (already backup), must not be applied to the new one.
+    for suffix in ["-wal", "-shm"] {
+        let journal = PathBuf::from(format!("{}{}", path.display(), suffix));
+        if journal.exists() {
+            std::fs::remove_file(&journal)?;
+        }
+    }
+    Ok(())
+}
+
+// bincode deserialize but rejects any trailing bytes, for tell the difference of older/newer layouts.
+fn strict_deserialize<T: DeserializeOwned>(bytes: &[u8]) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+}
+
+// wrap a (bincode encoded) value by Envelope of current schema version, and encrypt it if the database is encrypted.
+fn seal(body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    seal_with(body, CIPHER.read().unwrap().as_ref())
+}
+fn seal_with(body: Vec<u8>, cipher: Option<&Cipher>) -> anyhow::Result<Vec<u8>> {
+    let env = if let Some(c) = cipher {
+        Envelope { version: SCHEMA_VERSION, encrypted: true, body: c.encrypt(&body) }
+    } else {
+        Envelope { version: SCHEMA_VERSION, encrypted: false, body }
+    };
+    Ok( bincode::serialize(&env)? )
+}
+
+// unwrap the Envelope of a value, and decrypt it if encrypted. returns the bincode encoded value.
+pub fn open_envelope(raw: &[u8]) -> anyhow::Result<Vec<u8>> {
+    open_envelope_with(raw, CIPHER.read().unwrap().as_ref())
+}
+fn open_envelope_with(raw: &[u8], cipher: Option<&Cipher>) -> anyhow::Result<Vec<u8>> {
+    let env: Envelope = bincode::deserialize(raw)?;
+    if env.version != SCHEMA_VERSION {
+        return Err(anyhow::Error::msg(format!("record version {} is not equal to schema version {} (database not migrated?)", env.version, SCHEMA_VERSION)));
+    }
+    if ! env.encrypted {
+        return Ok(env.body);
+    }
+
+    let cipher = cipher.context("the record is encrypted, but the database is not unlocked by passphrase")?;
+    cipher.decrypt(&env.body).context("cannot decrypt the record (modified or corrupt)")
+}
+
+// Reads the benchmark calibration, None if never measured.
+pub fn load_calibration() -> anyhow::Result<Option<Calibration>> {
+    let dict = dict_open(TABLE_METADATA)?;
+    if let Some(raw) = dict.get(MD_CALIBRATION)? {
+        let mdata: Metadata = bincode::deserialize(&raw)?;
+        match mdata.kind {
+            MetadataKind::Calibration(c) => Ok(Some(c)),
+            _ => Err(anyhow::Error::msg("metadata type not equal Calibration")),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+// Stores (replaces) the benchmark calibration.
+pub fn save_calibration(c: &Calibration) -> anyhow::Result<()> {
+    let dict = dict_open(TABLE_METADATA)?;
+    let mdata = Metadata {
+        table: String::new(),
+        kind: MetadataKind::Calibration(c.clone()),
+        info: format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+    };
+    dict.insert(MD_CALIBRATION.to_vec(), bincode::serialize(&mdata)?)?;
+    dict.flush()?;
+    Ok(())
+}
+
+// Reads the recent round timings (oldest first).
+pub fn load_rounds() -> anyhow::Result<Vec<RoundTiming>> {
+    let dict = dict_open(TABLE_METADATA)?;
+    if let Some(raw) = dict.get(MD_ROUNDS)? {
+        let mdata: Metadata = bincode::deserialize(&raw)?;
+        match mdata.kind {
+            MetadataKind::Rounds(rounds) => Ok(rounds),
+            _ => Err(anyhow::Error::msg("metadata type not equal Rounds")),
+        }
+    } else {
+        Ok(vec![])
+    }
+}
+
+// Appends a round timing, only the recent MAX_ROUNDS are kept.
+pub fn add_round(rec: RoundTiming) -> anyhow::Result<()> {
+    // the older layout (without the speed source) cannot be used for the correction anyway
+    let mut rounds = load_rounds().unwrap_or_else(|e| {
+        log::warn!("discarded the un-readable round timings: {:?}", e);
+        vec![]
+    });
+    rounds.push(rec);
+    if rounds.len() > MAX_ROUNDS {
+        rounds.drain(.. rounds.len() - MAX_ROUNDS);
+    }
+
+    let dict = dict_open(TABLE_METADATA)?;
+    let mdata = Metadata {
+        table: String::new(),
+        kind: MetadataKind::Rounds(rounds),
+        info: String::new(),
+    };
+    dict.insert(MD_ROUNDS.to_vec(), bincode::serialize(&mdata)?)?;
+    dict.flush()?;
+    Ok(())
+}
+
+// Reads the encryption parameters of database, None if not encrypted.
+fn cipher_info() -> anyhow::Result<Option<CipherInfo>> {
+    let dict = dict_open(TABLE_METADATA)?;
+    if let Some(raw) = dict.get(MD_CIPHER)? {
+        let mdata: Metadata = bincode::deserialize(&raw)?;
+        match mdata.kind {
+            MetadataKind::Cipher(info) => Ok(Some(info)),
+            _ => Err(anyhow::Error::msg("metadata type not equal Cipher")),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+// Unlocks the encrypted database by passphrase (from env or prompt), does nothing if not encrypted. must be called after db_init().
+pub fn db_unlock() -> anyhow::Result<()> {
+    if let Some(info) = cipher_info()? {
+        let pass = dbcrypt::read_passphrase(dbcrypt::ENV_DB_PASSPHRASE, "the local database is encrypted, please enter passphrase")?;
+        let cipher = Cipher::open(&pass, &info).context("incorrect passphrase of database")?;
+        *CIPHER.write().unwrap() = Some(cipher);
+    }
+    Ok(())
+}
+
+// Re-encrypts all values (of all namespaces) by a new passphrase, or decrypt them if the new passphrase is empty. returns how many values re-encrypted.
+// the database must be unlocked first (if encrypted), and a backup will be saved before.
+// all values are re-encrypted into a new file which then replaces the current one, so an interrupted rekey never leaves a mixed-key database.
+pub fn db_rekey(new_passphrase: &str) -> anyhow::Result<usize> {
+    let old = CIPHER.read().unwrap().clone();
+    let (new, cipher) = if new_passphrase.is_empty() {
+        (None, None)
+    } else {
+        let (c, info) = Cipher::create(new_passphrase);
+        let mdata = Metadata {
+            table: String::new(),
+            kind: MetadataKind::Cipher(info),
+            info: String::new(),
+        };
+        (Some(c), Some(bincode::serialize(&mdata)?))
+    };
+
+    let backup = db_backup("rekey")?;
+    log::info!("database backup before rekey: {:?}", backup);
+
+    let tmp = PathBuf::from(format!("{}.rekey", db_path()?.display()));
+    let count = db_rewrite(&tmp, cipher, |raw| {
+        let body = open_envelope_with(raw, old.as_ref())?;
+        seal_with(body, new.as_ref())
+    })?;
+    db_replace(&tmp)?;
+
+    *CIPHER.write().unwrap() = new;
+    Ok(count)
+}
+
+// is this value already wrapped by current Envelope? (for migration can be re-run if it interrupted)
+fn is_sealed(raw: &[u8]) -> bool {
+    match strict_deserialize::<Envelope>(raw) {
+        Ok(env) => env.version == SCHEMA_VERSION,
+        Err(_) => false,
+    }
+}
+
+// wrap a value by the Envelope of version 1 (each migration writes the record format of its own version, never the current one)
+fn seal_v1(body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    Ok( bincode::serialize(&EnvelopeV1 { version: 1, body })? )
+}
+// is this value already wrapped by the Envelope of version 1? (for the migration v0 -> v1 can be re-run if it interrupted)
+fn is_sealed_v1(raw: &[u8]) -> bool {
+    match strict_deserialize::<EnvelopeV1>(raw) {
+        Ok(env) => env.version == 1,
+        Err(_) => false,
+    }
+}
+
+// wrap a value by the Envelope of version 2 (never encrypted, because no encryption before version 2)
+fn seal_v2(body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    Ok( bincode::serialize(&Envelope { version: 2, encrypted: false, body })? )
+}
+// is this value already wrapped by the Envelope of version 2? (for the migration v1 -> v2 can be re-run if it interrupted)
+fn is_sealed_v2(raw: &[u8]) -> bool {
+    match strict_deserialize::<Envelope>(raw) {
+        Ok(env) => env.version == 2,
+        Err(_) => false,
+    }
+}
+
+// version 0 -> 1: key list stored as Metadata (older stored raw HashSet), all values wrapped by Envelope, and the proof records converted to current layout.
+// (no namespaces before version 1, so only the shared tables)
+fn migrate_v0_v1() -> anyhow::Result<()> {
+    for table in TABLES {
+        let mut dm = DictMap::open_ns(table, None)?;
+        dm.migrate_keylist()?;
+
+        if *table == TABLE_PROOF_LIST {
+            let n = migrate_proofs(&mut dm)?;
+            log::info!("converted {} proof records", n);
+            continue;
+        }
+
+        for key in dm.keys()? {
+            if let Some(raw) = dm.get(&key)? {
+                if ! is_sealed_v1(&raw) {
+                    dm.set(&key, &seal_v1(raw)?)?;
+                }
+            }
+        }
+        dm.flush()?;
+    }
+    Ok(())
+}
+
+// version 1 -> 2: Envelope has the field "encrypted" (all false, because no encryption before version 2)
+fn migrate_v1_v2() -> anyhow::Result<()> {
+    for ns in namespaces()? {
+        for table in TABLES {
+            let mut dm = DictMap::open_ns(table, ns.as_deref())?;
+            for key in dm.keys()? {
+                if let Some(raw) = dm.get(&key)? {
+                    if is_sealed_v2(&raw) {
+                        continue;
+                    }
+                    let old: EnvelopeV1 = strict_deserialize(&raw).context("un-readable record of schema version 1")?;
+                    dm.set(&key, &seal_v2(old.body)?)?;
+                }
+            }
+            dm.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// Converts older proof records (TrySendProofV0 & TrySendProofStateV0) to the current layout and wraps by Envelope of version 1 (part of the migration v0 -> v1), returns how many records converted.
+// the unknown fields of older records are left zero, so the difficulty of them needs recover before submit.
+pub fn migrate_proofs(dm: &mut DictMap) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for key in dm.keys()? {
+        let raw = if let Some(v) = dm.get(&key)? { v } else { continue };
+        if is_sealed_v1(&raw) {
+            continue;
+        }
+
+        let new: TrySendProof =
+            if let Ok(k) = strict_deserialize::<TrySendProof>(&key) {
+                k
+            } else if let Ok(old) = strict_deserialize::<TrySendProofV0>(&key) {
+                TrySendProof {
+                    coin: old.coin,
+                    data: old.data,
+                    proof: old.proof,
+                    difficulty: 0,
+                    chi: HashVal::default(),
+                    thread: 0,
+                    elapsed: Duration::ZERO,
+                }
+            } else {
+                log::warn!("skip migrate an un-readable key of table {}", dm.name());
+                continue;
+            };
+
+        // the state may be already current layout (if written by a version between these changes)
+        let state: TrySendProofState =
+            if let Ok(st) = strict_deserialize::<TrySendProofState>(&raw) {
+                st
+            } else if let Ok(st) = strict_deserialize::<TrySendProofStateV0>(&raw) {
+                TrySendProofState {
+                    fails: st.fails,
+                    created: st.created,
+                    updated: SystemTime::now(),
+                    status:
+                        if st.sent {
+                            ProofStatus::Failed("sent by an older version (tx hash unknown)".to_string())
+                        } else if st.failed {
+                            ProofStatus::Failed("failed in an older version".to_string())
+                        } else {
+                            ProofStatus::Pending
+                        },
+                    errors: st.errors,
+                }
+            } else {
+                log::warn!("skip migrate an un-readable proof state {:?}", (new.coin, new.data));
+                continue;
+            };
+
+        let new_key = bincode::serialize(&new)?;
+        dm.set(&new_key, &seal_v1(bincode::serialize(&state)?)?)?;
+        if new_key != key {
+            dm.remove(&key)?;
+        }
+        count += 1;
+    }
+    dm.flush()?;
+    Ok(count)
+}
+
 /*
 pub struct Meta {
     inner: boringdb::Dict,
@@ -161,10 +984,15 @@ pub struct DictMap {
     name: String,
 }
 impl DictMap {
+    /// opens a table in the namespace of this process
     pub fn open(name: &str) -> anyhow::Result<Self> {
+        Self::open_ns(name, namespace().as_deref())
+    }
+    /// opens a table in specified namespace (None for the shared tables)
+    pub fn open_ns(name: &str, ns: Option<&str>) -> anyhow::Result<Self> {
         Ok(Self {
             md_keylist: (TABLE_METADATA.clone().to_owned()+".keys").as_bytes().to_vec(),
-            dict: Some( Arc::new(dict_open(name)?) ),
+            dict: Some( Arc::new(dict_open(&table_name(name, ns))?) ),
             name: name.to_string(),
         })
     }
@@ -207,27 +1035,20 @@ impl DictMap {
     }
 
     // checked key valid value. returns .to_vec value if valid, or anyhow::Error as rejected.
-    // 1. key starts with '.' or '_' is keep to internal used (.field / _data); forbidden use these as general data store.
-    //        (also allow suffix _ or middle . for user-specified)
-    // 2. current only limited for write access
+    // 1. the prefix of metadata (the key list "_metadata.keys") is keep to internal used; forbidden use it as general data store.
+    //        (the keys are bincode encoded binary, so any other first byte such as '_' or '.' is allowed)
+    // 2. empty key is invalid
+    // 3. current only limited for write access
     pub fn to_key(key: &[u8]) -> anyhow::Result<Vec<u8>> {
         let k = key.to_vec();
 
+        if k.is_empty() {
+            return Err(anyhow::Error::msg("invalid empty key"));
+        }
         if k.starts_with(TABLE_METADATA.clone().as_bytes()) {
             // Disallow modify to metadata
             return Err(anyhow::Error::msg("Try read/write to Internal metadata!"));
         }
-        match k[0] as char {
-            '_' => {
-                return Err(anyhow::Error::msg("Try access to Internal key prefix '_'"));
-            },
-            '.' => {
-                return Err(anyhow::Error::msg("invalid key starts with '.'"));
-            },
-            _ => {
-                // passing normal key
-            }
-        }
 
         Ok(k)
     }
@@ -244,10 +1065,85 @@ impl DictMap {
         dict.insert(k.clone(), value.to_vec())?;
         ks.insert(k);
 
-        dict.insert(self.md_keylist.clone(), bincode::serialize(&ks)?)?;
+        self.set_keys(ks)?;
         Ok( Some(()) )
     }
 
+    /// remove a key and its value, returns the old value if it exists.
+    pub fn remove(&mut self, key: &[u8]) -> anyhow::Result< Option<Vec<u8>> > {
+        if self.is_closed() {
+            return Err(anyhow::Error::msg("try remove from a closed dict map."));
+        }
+
+        let k = Self::to_key(key)?;
+        let mut ks = self.keys()?;
+
+        let dict = self.dict.as_ref().unwrap().as_ref();
+        let old = dict.remove(&k)?.map(|v| v.to_vec());
+        if ks.remove(&k) {
+            self.set_keys(ks)?;
+        }
+        Ok(old)
+    }
+
+    // overwrite the key list in metadata
+    fn set_keys(&self, ks: HashSet<Vec<u8>>) -> anyhow::Result<()> {
+        let dict = self.dict.as_ref().unwrap().as_ref();
+        let mdata = Metadata {
+            table: self.name.clone(),
+            kind: MetadataKind::KeyList(ks),
+            info: String::new(),
+        };
+        dict.insert(self.md_keylist.clone(), bincode::serialize(&mdata)?)?;
+        Ok(())
+    }
+
+    /// checks the key list of metadata matches the contents, returns (listed keys without value, keys that value is not a current Envelope)
+    /// (NOTE: boringdb cannot iterate the keys, so any value which not listed cannot be found)
+    pub fn verify_keys(&self) -> anyhow::Result<( Vec<Vec<u8>>, Vec<Vec<u8>> )> {
+        let dict = self.dict.as_ref().unwrap().as_ref();
+
+        let mut missing = vec![];
+        let mut corrupt = vec![];
+        for k in self.keys()? {
+            match dict.get(&k)? {
+                None => missing.push(k),
+                Some(v) => {
+                    if ! is_sealed(&v) {
+                        corrupt.push(k);
+                    }
+                },
+            }
+        }
+        Ok((missing, corrupt))
+    }
+
+    /// removes some keys from the key list only (does not touch values), used for repair the missing keys of verify_keys.
+    pub fn unlist_keys(&mut self, keys: &[Vec<u8>]) -> anyhow::Result<usize> {
+        let mut ks = self.keys()?;
+        let mut count = 0;
+        for k in keys {
+            if ks.remove(k) {
+                count += 1;
+            }
+        }
+        self.set_keys(ks)?;
+        Ok(count)
+    }
+
+    // convert the key list of older version (raw HashSet) to Metadata, used by migration only.
+    fn migrate_keylist(&self) -> anyhow::Result<()> {
+        let dict = self.dict.as_ref().unwrap().as_ref();
+        if let Some(raw) = dict.get(&self.md_keylist)? {
+            if strict_deserialize::<Metadata>(&raw).is_ok() {
+                return Ok(());
+            }
+            let ks: HashSet<Vec<u8>> = bincode::deserialize(&raw).context("un-readable key list")?;
+            self.set_keys(ks)?;
+        }
+        Ok(())
+    }
+
     pub fn keys(&self) -> anyhow::Result< HashSet<Vec<u8>> > {
         let dict = self.dict.as_ref().unwrap().as_ref();
         if let Some(mdata) = dict.get(&self.md_keylist)? {
@@ -315,7 +1211,7 @@ impl Map {
     pub fn get<K: Serialize, V: DeserializeOwned>(&self, key: K) -> anyhow::Result<Option< Box<V> >> {
         let k = bincode::serialize(&key)?;
         if let Some(v) = self.cur().get(&k)? {
-            let out: V = bincode::deserialize(&v)?;
+            let out: V = bincode::deserialize(&open_envelope(&v)?)?;
             Ok(Some( Box::new(out) ))
         } else {
             Ok(None)
@@ -323,10 +1219,14 @@ impl Map {
     }
     pub fn set<K: Serialize, V: Serialize>(&mut self, key: K, value: V) -> anyhow::Result<()> {
         let k = bincode::serialize(&key)?;
-        let v = bincode::serialize(&value)?;
-        self.cur().set( &k, &v );
+        let v = seal(bincode::serialize(&value)?)?;
+        self.cur().set( &k, &v )?;
         Ok(())
     }
+    pub fn remove<K: Serialize>(&mut self, key: K) -> anyhow::Result<bool> {
+        let k = bincode::serialize(&key)?;
+        Ok( self.cur().remove(&k)?.is_some() )
+    }
 
     /// changes current dict mapping to specified name
     pub fn dict(&mut self, name: &str) -> anyhow::Result<()> {
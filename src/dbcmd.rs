@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::cmdopts::DbCmd;
+use crate::dbcrypt;
+use crate::db::{
+    self, DictMap, LogRecord, Map, ProofStatus,
+    SeedRecord, SeedSweep, TrySendProof, TrySendProofState,
+    TABLES, TABLE_LOGS, TABLE_NEWCOINS, TABLE_PROOF_LIST,
+};
+
+/// Runs a database maintenance subcommand.
+pub fn run(cmd: DbCmd) -> anyhow::Result<()> {
+    match cmd {
+        DbCmd::Tables => {
+            println!("database: {:?} (schema version {})", db::db_path()?, db::schema_version()?);
+            for ns in db::namespaces()? {
+                println!("[{}]", ns.as_deref().unwrap_or("(shared tables of older versions)"));
+                for table in TABLES {
+                    let dm = DictMap::open_ns(table, ns.as_deref())?;
+                    println!("{}\t{} keys", table, dm.keys()?.len());
+                }
+            }
+        },
+
+        DbCmd::Dump { table } => {
+            let out = dump(&table)?;
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        },
+
+        DbCmd::ExportProofs { file } => {
+            // the exported proofs are marked, so no any minter should be running.
+            let _lock = db::db_lock(db::namespace().as_deref())?;
+            let mut map = Map::new();
+            map.dict(TABLE_PROOF_LIST)?;
+
+            let pending: Vec<(TrySendProof, TrySendProofState)> =
+                db::load_proofs(&map)?
+                .into_iter()
+                .filter(|(_, tryst)| tryst.status == ProofStatus::Pending)
+                .collect();
+
+            let f = File::create(&file).context("cannot create export file")?;
+            serde_json::to_writer_pretty(f, &pending)?;
+
+            // only the other machine submits them now
+            for (trys, mut tryst) in pending.iter().cloned() {
+                tryst.status = ProofStatus::Exported(file.display().to_string());
+                tryst.updated = SystemTime::now();
+                map.set(&trys, &tryst)?;
+            }
+            map.flush()?;
+            println!("exported {} pending proofs to {:?} (marked as exported in this database)", pending.len(), file);
+        },
+
+        DbCmd::ImportProofs { file } => {
+            let f = File::open(&file).context("cannot open import file")?;
+            let proofs: Vec<(TrySendProof, TrySendProofState)> = serde_json::from_reader(f)?;
+
+            let _lock = db::db_lock(db::namespace().as_deref())?;
+            let mut map = Map::new();
+            map.dict(TABLE_PROOF_LIST)?;
+
+            let mut count = 0;
+            for (trys, tryst) in proofs {
+                if map.get::<_, TrySendProofState>(&trys)?.is_some() {
+                    log::info!("skip an existing proof {:?}", trys.coin);
+                    continue;
+                }
+                map.set(&trys, &tryst)?;
+                count += 1;
+            }
+            map.flush()?;
+            println!("imported {} proofs from {:?}", count, file);
+        },
+
+        DbCmd::Verify { repair } => {
+            let mut ok = true;
+            for ns in db::namespaces()? {
+                let ns = ns.as_deref();
+                let _lock = if repair { Some(db::db_lock(ns)?) } else { None };
+
+                println!("[{}]", ns.unwrap_or("(shared tables of older versions)"));
+                for table in TABLES {
+                    let mut dm = DictMap::open_ns(table, ns)?;
+                    let (missing, corrupt) = dm.verify_keys()?;
+                    println!("{}\t{} keys listed, {} missing value, {} corrupt value", table, dm.keys()?.len(), missing.len(), corrupt.len());
+
+                    if ! missing.is_empty() {
+                        if repair {
+                            let n = dm.unlist_keys(&missing)?;
+                            dm.flush()?;
+                            println!("{}\trepaired: removed {} missing keys from key list", table, n);
+                        } else {
+                            ok = false;
+                        }
+                    }
+                    if ! corrupt.is_empty() {
+                        ok = false;
+                    }
+                }
+            }
+
+            if ! ok {
+                return Err(anyhow::Error::msg("database verification failed (use --repair for remove missing keys; the corrupt values need manual check)"));
+            }
+        },
+
+        DbCmd::Rekey => {
+            // all values will be re-written, so no any minter should be running.
+            let mut _locks = vec![];
+            for ns in db::namespaces()? {
+                _locks.push(db::db_lock(ns.as_deref())?);
+            }
+
+            let new = dbcrypt::read_passphrase(dbcrypt::ENV_DB_NEW_PASSPHRASE, "new passphrase of database (empty for disable encryption)")?;
+            if ! new.is_empty() {
+                let confirm = dbcrypt::read_passphrase(dbcrypt::ENV_DB_NEW_PASSPHRASE, "confirm new passphrase")?;
+                if confirm != new {
+                    return Err(anyhow::Error::msg("the passphrases you entered do not match"));
+                }
+            }
+
+            let n = db::db_rekey(&new)?;
+            println!("re-encrypted {} records, the database is {}", n, if new.is_empty() { "not encrypted now" } else { "encrypted by new passphrase" });
+        },
+
+        DbCmd::Compact => {
+            // the whole file will be replaced, so no any minter should be running.
+            let mut _locks = vec![];
+            for ns in db::namespaces()? {
+                _locks.push(db::db_lock(ns.as_deref())?);
+            }
+
+            let (before, after) = db::db_compact()?;
+            println!("compacted database: {} bytes -> {} bytes", before, after);
+        },
+    }
+    Ok(())
+}
+
+// reads all records of a table as JSON values. the known tables are decoded, others are dumped as raw bytes.
+fn dump(table: &str) -> anyhow::Result<serde_json::Value> {
+    if ! TABLES.contains(&table) {
+        return Err(anyhow::Error::msg(format!("unknown table {}, should be one of {:?}", table, TABLES)));
+    }
+
+    let mut map = Map::new();
+    map.dict(table)?;
+
+    let mut out = vec![];
+    if table == TABLE_PROOF_LIST {
+        for (trys, tryst) in db::load_proofs(&map)? {
+            out.push(json!({ "key": trys, "value": tryst }));
+        }
+    } else if table == TABLE_LOGS {
+        for key in map.cur().keys()? {
+            let k: String = bincode::deserialize(&key)?;
+            let v: Option<Box<LogRecord>> = map.get(&k)?;
+            out.push(json!({ "key": k, "value": v }));
+        }
+    } else if table == TABLE_NEWCOINS {
+        for key in map.cur().keys()? {
+            let k: String = bincode::deserialize(&key)?;
+            let v = if k.starts_with("sweep.") {
+                json!(map.get::<_, SeedSweep>(&k)?)
+            } else {
+                json!(map.get::<_, SeedRecord>(&k)?)
+            };
+            out.push(json!({ "key": k, "value": v }));
+        }
+    } else {
+        for key in map.cur().keys()? {
+            let v: Option<Vec<u8>> = match map.cur().get(&key)? {
+                Some(raw) => Some(db::open_envelope(&raw)?),
+                None => None,
+            };
+            out.push(json!({ "key": key, "value": v }));
+        }
+    }
+    Ok(serde_json::Value::Array(out))
+}
@@ -0,0 +1,97 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Serialize, Deserialize};
+use tmelcrypt::{hash_keyed, HashVal};
+
+// environment variable of the database passphrase, otherwise prompt for it.
+pub const ENV_DB_PASSPHRASE: &str = "MELMINTER_DB_PASSPHRASE";
+// environment variable of the new passphrase for "db rekey", otherwise prompt for it.
+pub const ENV_DB_NEW_PASSPHRASE: &str = "MELMINTER_DB_NEW_PASSPHRASE";
+
+// how many rounds of hashing to derive the key from passphrase (make brute-force guessing slower)
+const KDF_ROUNDS: u32 = 200_000;
+
+// a known message for checks the passphrase is correct
+const CHECK_MSG: &[u8] = b"melminter-mod database passphrase check";
+
+// the nonce size of ChaCha20-Poly1305
+const NONCE_LEN: usize = 12;
+
+/// the parameters of key derivation, stored in metadata of database (no any secrets)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CipherInfo {
+    pub salt: HashVal,
+    pub rounds: u32,
+    pub check: Vec<u8>, // CHECK_MSG encrypted by the key, for tell whether the passphrase is correct
+}
+
+/// Encrypts/decrypts the values of database, by a key derived from passphrase.
+/// (ChaCha20-Poly1305 AEAD, the tag is verified by the crate in constant time)
+/// format of sealed bytes: nonce(12) | ciphertext | tag(16)
+#[derive(Clone)]
+pub struct Cipher {
+    aead: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// derives a new cipher from passphrase with a random salt, returns the parameters that should be stored.
+    pub fn create(passphrase: &str) -> (Self, CipherInfo) {
+        let salt = HashVal::random();
+        let cipher = Self::derive(passphrase, salt, KDF_ROUNDS);
+        let info = CipherInfo {
+            salt,
+            rounds: KDF_ROUNDS,
+            check: cipher.encrypt(CHECK_MSG),
+        };
+        (cipher, info)
+    }
+
+    /// derives the cipher from passphrase and stored parameters, returns None if the passphrase is incorrect.
+    pub fn open(passphrase: &str, info: &CipherInfo) -> Option<Self> {
+        let cipher = Self::derive(passphrase, info.salt, info.rounds);
+        // a wrong key cannot pass the authentication of AEAD
+        if cipher.decrypt(&info.check)? == CHECK_MSG {
+            Some(cipher)
+        } else {
+            None
+        }
+    }
+
+    fn derive(passphrase: &str, salt: HashVal, rounds: u32) -> Self {
+        let mut key = hash_keyed(&salt, passphrase.as_bytes());
+        for _ in 0..rounds {
+            key = hash_keyed(&salt, &key);
+        }
+        Self {
+            aead: ChaCha20Poly1305::new(Key::from_slice(&key.0)),
+        }
+    }
+
+    pub fn encrypt(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self.aead.encrypt(&nonce, plain).expect("ChaCha20-Poly1305 encryption never fails for a Vec buffer");
+
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        out
+    }
+
+    /// returns None if the data is truncated or modified (or encrypted by another key).
+    pub fn decrypt(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, data) = sealed.split_at(NONCE_LEN);
+        self.aead.decrypt(Nonce::from_slice(nonce), data).ok()
+    }
+}
+
+/// reads a passphrase from environment variable, otherwise prompt for it.
+pub fn read_passphrase(env: &str, prompt: &str) -> anyhow::Result<String> {
+    if let Ok(p) = std::env::var(env) {
+        return Ok(p);
+    }
+    Ok( rpassword::prompt_password(format!("{} (or set env {}): ", prompt, env))? )
+}
@@ -0,0 +1,70 @@
+/// Throttles the mint threads by system load and CPU temperature (only readable on Linux)
+#[derive(Copy, Clone, Debug)]
+pub struct Governor {
+    pub max_load: Option<f64>, // the load average (1 minute) of other programs, excludes our mint threads
+    pub max_temp: Option<f64>, // unit: Celsius
+}
+
+// resume a thread only if the temperature is lower than max_temp by this
+const TEMP_HYSTERESIS: f64 = 5.0;
+
+/// the load average of 1 minute
+pub fn read_loadavg() -> Option<f64> {
+    std::fs::read_to_string("/proc/loadavg").ok()?
+        .split_whitespace().next()?
+        .parse().ok()
+}
+
+/// the highest temperature (Celsius) of all thermal zones
+pub fn read_temperature() -> Option<f64> {
+    let mut max: Option<f64> = None;
+    for entry in std::fs::read_dir("/sys/class/thermal").ok()?.flatten() {
+        if ! entry.file_name().to_string_lossy().starts_with("thermal_zone") {
+            continue;
+        }
+        // unit: millidegree Celsius
+        let t: f64 = match std::fs::read_to_string(entry.path().join("temp")).ok().and_then(|s| s.trim().parse().ok()) {
+            Some(t) => t,
+            None => { continue; }
+        };
+        let t = t / 1000.0;
+        if max.map(|m| t > m).unwrap_or(true) {
+            max = Some(t);
+        }
+    }
+    max
+}
+
+impl Governor {
+    pub fn enabled(&self) -> bool {
+        self.max_load.is_some() || self.max_temp.is_some()
+    }
+
+    /// how many mint threads should be active, by the current load (includes our `active` threads) and temperature.
+    /// steps one by one (or a quarter of threads if too hot), so the load average has time to follow.
+    pub fn decide(&self, load: Option<f64>, temp: Option<f64>, active: usize, threads: usize) -> usize {
+        let other_load = load.map(|l| (l - active as f64).max(0.0));
+
+        let too_hot = matches!((self.max_temp, temp), (Some(max), Some(t)) if t > max);
+        let too_busy = matches!((self.max_load, other_load), (Some(max), Some(l)) if l > max);
+        if too_hot {
+            return active.saturating_sub((active / 4).max(1));
+        }
+        if too_busy {
+            return active.saturating_sub(1);
+        }
+
+        let cool = match (self.max_temp, temp) {
+            (Some(max), Some(t)) => t < max - TEMP_HYSTERESIS,
+            _ => true,
+        };
+        let idle = match (self.max_load, other_load) {
+            (Some(max), Some(l)) => l + 1.0 <= max,
+            _ => true,
+        };
+        if cool && idle && active < threads {
+            return active + 1;
+        }
+        active.min(threads)
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use fs2::FileExt;
+
+use crate::db;
+
+/// Single-instance lock of the minter for a wallet & daemon, with a PID file for tell which process holds it.
+/// the lock will be released if dropped or the process exited.
+pub struct InstanceLock {
+    _file: File,
+    pub pid_path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Acquires the lock, returns error (naming the other process) if another minter is already running for this wallet & daemon.
+    pub fn acquire(wallet: &str, daemon: SocketAddr) -> anyhow::Result<Self> {
+        let dir = lock_dir()?;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .create(&dir)?;
+
+        let name: String = format!("{}@{}", wallet, daemon).chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+            .collect();
+        let lock_path = dir.join(format!("{}.lock", name));
+        let pid_path = dir.join(format!("{}.pid", name));
+
+        let file = OpenOptions::new().create(true).write(true).open(&lock_path)?;
+        if file.try_lock_exclusive().is_err() {
+            // first line is the PID, second line is the command line
+            let other = match std::fs::read_to_string(&pid_path) {
+                Ok(s) => {
+                    let mut lines = s.lines();
+                    format!("pid {} ({})", lines.next().unwrap_or("?"), lines.next().unwrap_or(""))
+                },
+                Err(_) => "unknown process (PID file not found)".to_string(),
+            };
+            return Err(anyhow::Error::msg(format!(
+                "another {} is already running for wallet {} of daemon {}: {}. (lock file {:?})",
+                env!("CARGO_PKG_NAME"), wallet, daemon, other, lock_path
+            )));
+        }
+
+        // the process exited without Drop (for example by process::exit), its PID file is stale now
+        if let Ok(stale) = std::fs::read_to_string(&pid_path) {
+            log::warn!("overwriting the stale PID file {:?} (pid {} exited without removing it)", pid_path, stale.lines().next().unwrap_or("?"));
+        }
+
+        let mut pid_file = OpenOptions::new();
+        pid_file.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut pid_file, 0o600);
+        pid_file.open(&pid_path)
+            .and_then(|mut f| write!(f, "{}\n{}\n", std::process::id(), redacted_args().join(" ")))
+            .context("cannot write PID file")?;
+
+        Ok(Self {
+            _file: file,
+            pid_path,
+        })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.pid_path);
+    }
+}
+
+// the lock files are always stored in the OS config directory: never beside the database file,
+// otherwise two minters with different --data-dir (or --db-path) would both get the lock for the same wallet & daemon.
+fn lock_dir() -> anyhow::Result<PathBuf> {
+    db::confdir().context("cannot find a directory for lock files")
+}
+
+// the command line for the PID file, without any secret (the value of --import-sk)
+fn redacted_args() -> Vec<String> {
+    let mut args: Vec<String> = std::env::args().collect();
+    for i in 0 .. args.len() {
+        if args[i] == "--import-sk" && i+1 < args.len() {
+            args[i+1] = "<redacted>".to_string();
+        } else if args[i].starts_with("--import-sk=") {
+            args[i] = "--import-sk=<redacted>".to_string();
+        }
+    }
+    args
+}
@@ -2,7 +2,7 @@ use std::{ future::Future, time::Duration, net::SocketAddr };
 use std::io::Write;
 
 use anyhow::Context;
-use cmdopts::CmdOpts;
+use cmdopts::{CmdOpts, Command};
 
 use melwallet_client::DaemonClient;
 use prodash::{
@@ -10,29 +10,29 @@ use prodash::{
     Tree,
 };
 use structopt::StructOpt;
-use themelio_structs::{CoinValue, NetID};
+use themelio_structs::{Address, CoinValue, NetID};
 
 mod cmdopts;
 mod state;
 mod worker;
+mod dbcmd;
+mod dbcrypt;
+mod instance;
+mod affinity;
+mod schedule;
+mod control;
+mod governor;
+mod bench;
+mod backend;
 
 #[allow(unused)]
 mod db;
 
 // use smol::prelude::*;
 use crate::worker::{Worker, WorkerConfig};
+use crate::state::WalletPassword;
 
 fn main() -> surf::Result<()> {
-    let dash_root = Tree::default();
-    let dash_options = line::Options {
-        keep_running_if_progress_is_empty: true,
-        throughput: true,
-        // hide_cursor: true,
-        ..Default::default()
-    }
-    .auto_configure(StreamKind::Stdout);
-    let _handle = line::render(std::io::stdout(), dash_root.clone(), dash_options);
-
     let opts: CmdOpts = CmdOpts::from_args();
     {
         let mut lb = env_logger::Builder::new();
@@ -45,11 +45,120 @@ fn main() -> surf::Result<()> {
         }
         lb.init();
     }
+    // the cancelled mint threads are not errors
+    control::install_panic_hook();
 
     if opts.daemon.is_some() && opts.endpoint.is_some() {
         panic_exit!(2, "unexpected found both option --daemon and --endpoint given");
     }
 
+    if opts.import_sk.is_some() && opts.import_sk_file.is_some() {
+        panic_exit!(2, "unexpected found both option --import-sk and --import-sk-file given");
+    }
+    if opts.data_dir.is_some() && opts.db_path.is_some() {
+        panic_exit!(2, "unexpected found both option --data-dir and --db-path given");
+    }
+    if let Some(path) = opts.db_path.clone() {
+        db::set_db_path(path);
+    } else if let Some(mut dir) = opts.data_dir.clone() {
+        dir.push(db::DB_FILENAME);
+        db::set_db_path(dir);
+    }
+
+    // make sure the local database is ready (refuse a newer one, or migrate older one) before anything to do
+    if let Err(e) = db::db_init() {
+        panic_exit!(3, "cannot initialize the local database: {:?}", e);
+    }
+    // if the database is encrypted, unlock it by passphrase
+    if let Err(e) = db::db_unlock() {
+        panic_exit!(3, "cannot unlock the local database: {:?}", e);
+    }
+
+    // run the maintenance subcommands without minting (and before the progress dashboard, to keep the stdout clean)
+    if let Some(Command::Db(cmd)) = opts.cmd.clone() {
+        // without network, the shared tables (of older versions) will be used.
+        if let Some(netid) = opts.network {
+            if let Err(e) = db::set_namespace(netid, &mint_wallet_name(&opts, netid)) {
+                panic_exit!(3, "cannot initialize the local database: {:?}", e);
+            }
+        }
+
+        if let Err(e) = dbcmd::run(cmd) {
+            panic_exit!(4, "database maintenance failed: {:?}", e);
+        }
+        return Ok(());
+    }
+
+    // the CPUs for mint threads if pinning, and how many threads
+    let (cpu_plan, threads): (Option<Vec<usize>>, usize) = if opts.pin_threads || opts.cpus.is_some() {
+        match affinity::plan(&affinity::topology(), opts.cpus.as_ref()) {
+            Ok((cpus, cores)) => {
+                let threads = opts.threads.unwrap_or(cores);
+                log::info!("mint threads ({}) will be pinned to CPUs {:?}", threads, cpus.iter().cycle().take(threads).collect::<Vec<_>>());
+                (Some(cpus), threads)
+            },
+            Err(e) => {
+                panic_exit!(2, "cannot pin mint threads: {:?}", e);
+            }
+        }
+    } else {
+        (None, opts.threads.unwrap_or_else(num_cpus::get_physical))
+    };
+
+    if let Some(Command::Bench { difficulties, no_save }) = opts.cmd.clone() {
+        let calibration = bench::run(&difficulties, threads, cpu_plan.as_deref());
+        if ! no_save {
+            if let Err(e) = db::save_calibration(&calibration) {
+                panic_exit!(4, "cannot store the calibration: {:?}", e);
+            }
+            println!("calibration stored to {:?}", db::db_path()?);
+        }
+        return Ok(());
+    }
+
+    // read the wallet password before the progress dashboard started (it may prompt)
+    let wallet_password: Option<WalletPassword> = match read_wallet_password(&opts) {
+        Ok(pw) => pw,
+        Err(e) => {
+            panic_exit!(6, "cannot read the password of mint wallet: {:?}", e);
+        }
+    };
+
+    let import_sk: Option<String> =
+        if let Some(sk) = opts.import_sk.clone() {
+            Some(sk.trim().to_string())
+        } else if let Some(path) = &opts.import_sk_file {
+            match std::fs::read_to_string(path) {
+                Ok(sk) => Some(sk.trim().to_string()),
+                Err(e) => {
+                    panic_exit!(6, "cannot read the secret key file {:?}: {:?}", path, e);
+                }
+            }
+        } else {
+            None
+        };
+    // the address of imported secret key
+    let import_addr: Option<Address> = if let Some(sk) = &import_sk {
+        match sk_address(sk) {
+            Ok(addr) => Some(addr),
+            Err(e) => {
+                panic_exit!(6, "invalid secret key to import: {:?}", e);
+            }
+        }
+    } else {
+        None
+    };
+
+    let dash_root = Tree::default();
+    let dash_options = line::Options {
+        keep_running_if_progress_is_empty: true,
+        throughput: true,
+        // hide_cursor: true,
+        ..Default::default()
+    }
+    .auto_configure(StreamKind::Stdout);
+    let _handle = line::render(std::io::stdout(), dash_root.clone(), dash_options);
+
     let melwalletd_addr: SocketAddr =
         if let Some(addr) = opts.daemon {
             addr
@@ -94,22 +203,63 @@ fn main() -> surf::Result<()> {
         let is_testnet = netid != NetID::Mainnet;
 
         // generate wallet name for minting
-        let wallet_name = format!("{}{:?}", opts.wallet_prefix, netid);
+        let wallet_name = mint_wallet_name(&opts, netid);
+
+        // only one minter for each wallet & daemon, otherwise they will double-spend coins and fight over the proof queue.
+        let _instance = match instance::InstanceLock::acquire(&wallet_name, melwalletd_addr) {
+            Ok(lock) => lock,
+            Err(e) => {
+                panic_exit!(5, "{:?}", e);
+            }
+        };
+
+        // use the tables of this network & wallet only, and make sure no other minter is using them.
+        let _db_lock = {
+            let ns = db::set_namespace(netid, &wallet_name)?;
+            match db::db_lock(Some(&ns)) {
+                Ok(lock) => lock,
+                Err(e) => {
+                    panic_exit!(5, "{:?}", e);
+                }
+            }
+        };
+        // the proofs of older versions are stored in the shared tables, which network they belong to is unknown...
+        let shared_proofs = db::DictMap::open_ns(db::TABLE_PROOF_LIST, None)?.keys()?.len();
+        if shared_proofs > 0 {
+            log::warn!("found {} proof records in the shared table of older versions, they will not be submitted automatically.", shared_proofs);
+            log::warn!("if they belong to this network & wallet, please move them by: 'db export-proofs <file>' (without --network) and then 'db import-proofs <file> --network {}'", netid);
+        }
         // make sure the working-wallet exists
         let worker_wallet = match daemon.get_wallet(&wallet_name).await? {
             Some(wallet) => wallet,
             None => {
                 let mut evt = dash_root.add_child(format!("creating new wallet {}", wallet_name));
                 evt.init(None, None);
-                log::info!("creating new wallet");
-                daemon.create_wallet(&wallet_name, is_testnet, None, None).await?;
+                if import_sk.is_some() {
+                    log::info!("creating new wallet from the imported secret key");
+                } else {
+                    log::info!("creating new wallet");
+                }
+                daemon.create_wallet(&wallet_name, is_testnet, wallet_password.as_ref().map(|pw| pw.0.clone()), import_sk.clone()).await?;
                 daemon.get_wallet(&wallet_name).await?.context("just-created wallet gone?!")?
             }
         };
+        std::mem::drop(import_sk);
+
+        // the working-wallet must be the imported one, never overwrite (or use) a differently-keyed wallet
+        if let Some(addr) = import_addr {
+            let actual = worker_wallet.summary().await?.address;
+            if actual != addr {
+                panic_exit!(7, "the mint wallet {} already exists with address {}, but the imported secret key is address {}! refused to use it, please use another --wallet-prefix.", wallet_name, actual, addr);
+            }
+            log::info!("the mint wallet {} is the imported secret key (address {})", wallet_name, addr);
+        }
 
         if let None = opts.payout {
             let wallet_sk = if opts.export_sk {
-                if let Ok(sk) = worker_wallet.export_sk(None).await {
+                if let Some(pw) = &wallet_password {
+                    worker_wallet.export_sk(Some(pw.0.clone())).await?
+                } else if let Ok(sk) = worker_wallet.export_sk(None).await {
                     sk
                 } else {
                     worker_wallet.export_sk(Some("".to_string())).await?
@@ -146,12 +296,14 @@ fn main() -> surf::Result<()> {
 
         let worker = Worker::start(WorkerConfig {
             wallet: worker_wallet,
+            wallet_password,
             payout: opts.payout,
             connect: if let Some(bootstrap) = opts.bootstrap { bootstrap } else { themelio_bootstrap::bootstrap_routes(netid)[0] },
             netid,
             //name: "".into(),
             tree: dash_root.clone(),
-            threads: opts.threads.unwrap_or_else(num_cpus::get_physical),
+            threads,
+            cpus: cpu_plan,
 
             cli_opts: opts.clone(),
         });
@@ -164,7 +316,7 @@ fn main() -> surf::Result<()> {
             }
 
             worker_stopping = true;
-            log::warn!("Received Ctrl+C key, the program will stopping mint as soon as possible... scheduled to stop after the DoscMint transactions sent, or you can exit immediately (by press again) if you wish.");
+            log::warn!("Received Ctrl+C key, the program will stopping mint as soon as possible... the running mint threads are cancelled, and scheduled to stop after the DoscMint transactions of finished proofs sent, or you can exit immediately (by press again) if you wish.");
             smol::block_on(worker.stop()).unwrap();
         }).unwrap();
 
@@ -186,6 +338,32 @@ macro_rules! panic_exit {
     };
 }
 
+// environment variable of the mint wallet password
+const ENV_WALLET_PASSWORD: &str = "MELMINTER_WALLET_PASSWORD";
+
+// reads the password of mint wallet: from file, env var, or prompt (if requested). None for a wallet without password.
+fn read_wallet_password(opts: &CmdOpts) -> anyhow::Result<Option<WalletPassword>> {
+    if let Some(path) = &opts.wallet_password_file {
+        let pw = std::fs::read_to_string(path).context("cannot read the wallet password file")?;
+        // ignore the line ending of file
+        let pw = pw.trim_end_matches(&['\r', '\n'][..]).to_string();
+        return Ok(Some( WalletPassword(pw) ));
+    }
+    if let Ok(pw) = std::env::var(ENV_WALLET_PASSWORD) {
+        return Ok(Some( WalletPassword(pw) ));
+    }
+    if opts.ask_wallet_password {
+        let pw = rpassword::prompt_password("password of mint wallet: ")?;
+        return Ok(Some( WalletPassword(pw) ));
+    }
+    Ok(None)
+}
+
+// the name of working-wallet for minting
+fn mint_wallet_name(opts: &CmdOpts, netid: NetID) -> String {
+    format!("{}{:?}", opts.wallet_prefix, netid)
+}
+
 /// Repeats something until it stops failing
 async fn repeat_fallible<T, E: std::fmt::Debug, F: Future<Output = Result<T, E>>>(
     mut clos: impl FnMut() -> F,
@@ -199,6 +377,13 @@ async fn repeat_fallible<T, E: std::fmt::Debug, F: Future<Output = Result<T, E>>
     }
 }
 
+/// Get the address of a (hex encoded) ed25519 secret key
+pub fn sk_address(sk: &str) -> anyhow::Result<Address> {
+    let bytes = hex::decode(sk)?;
+    let sk = tmelcrypt::Ed25519SK::from_bytes(&bytes).context("not an ed25519 secret key")?;
+    Ok( themelio_stf::melvm::Covenant::std_ed25519_pk_new(sk.to_public()).hash() )
+}
+
 /// Generate a new owner-less address. any coins that are sent to such addresses are considered lost forever, "the dead end of blockchain"
 pub fn new_void_address() -> themelio_structs::Address {
     themelio_stf::melvm::Covenant::std_ed25519_pk_new(
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use chrono::{Datelike, Timelike};
+
+const DAY: u32 = 24 * 60;
+const WEEK: u32 = 7 * DAY;
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A time window of minting, for example "Mon-Fri 18:00-08:00" (crossing midnight is allowed, it belongs to the start day)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+    days: [bool; 7], // Monday first
+    start: u32, // minute of day
+    end: u32, // minute of day (up to 24:00)
+}
+
+/// The time windows of minting (local time), separated by ";", for example "Mon-Fri 18:00-08:00; Sat,Sun 00:00-24:00"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule(pub Vec<Window>);
+
+fn parse_day(s: &str) -> Result<usize, String> {
+    let s = s.trim().to_ascii_lowercase();
+    DAY_NAMES.iter().position(|d| s.starts_with(d))
+        .ok_or_else(|| format!("unknown day {:?}", s))
+}
+
+fn parse_time(s: &str) -> Result<u32, String> {
+    let (h, m) = s.trim().split_once(':').ok_or_else(|| format!("invalid time {:?}, should be HH:MM", s))?;
+    let h: u32 = h.parse().map_err(|_| format!("invalid hour of {:?}", s))?;
+    let m: u32 = m.parse().map_err(|_| format!("invalid minute of {:?}", s))?;
+    if m >= 60 || h * 60 + m > DAY {
+        return Err(format!("invalid time {:?}", s));
+    }
+    Ok(h * 60 + m)
+}
+
+impl std::str::FromStr for Window {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let (days_str, range) = match tokens.len() {
+            1 => ("*", tokens[0]),
+            2 => (tokens[0], tokens[1]),
+            _ => { return Err(format!("invalid window {:?}, should be like \"Mon-Fri 18:00-08:00\"", s)); }
+        };
+
+        let mut days = [false; 7];
+        for item in days_str.split(',') {
+            if item.trim() == "*" {
+                days = [true; 7];
+            } else if let Some((a, b)) = item.split_once('-') {
+                let (a, b) = (parse_day(a)?, parse_day(b)?);
+                // wrap-around is allowed, for example "Fri-Mon"
+                let mut d = a;
+                loop {
+                    days[d] = true;
+                    if d == b { break; }
+                    d = (d + 1) % 7;
+                }
+            } else {
+                days[parse_day(item)?] = true;
+            }
+        }
+
+        let (start, end) = range.split_once('-').ok_or_else(|| format!("invalid time range {:?}, should be like 18:00-08:00", range))?;
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        if start == end {
+            return Err(format!("empty time range {:?}", range));
+        }
+        Ok(Self { days, start, end })
+    }
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let windows = s.split(';')
+            .filter(|w| ! w.trim().is_empty())
+            .map(|w| w.parse())
+            .collect::<Result<Vec<Window>, String>>()?;
+        if windows.is_empty() {
+            return Err("empty schedule".to_string());
+        }
+        Ok(Self(windows))
+    }
+}
+
+/// the current local time as (minute of week, second of minute), Monday 00:00 is zero.
+pub fn now() -> (u32, u32) {
+    let t = chrono::Local::now();
+    (t.weekday().num_days_from_monday() * DAY + t.hour() * 60 + t.minute(), t.second())
+}
+
+impl Schedule {
+    /// whether a minute of week is inside any window
+    pub fn contains(&self, minute: u32) -> bool {
+        let minute = minute % WEEK;
+        for w in &self.0 {
+            for (d, enabled) in w.days.iter().enumerate() {
+                if ! enabled { continue; }
+                let start = d as u32 * DAY + w.start;
+                let end = d as u32 * DAY + if w.end > w.start { w.end } else { w.end + DAY };
+                // the window of Sunday may cross the end of week
+                if (start..end).contains(&minute) || (start..end).contains(&(minute + WEEK)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// how long the current window lefts, None if outside of all windows. (Duration::MAX if always inside)
+    pub fn remaining(&self, (minute, second): (u32, u32)) -> Option<Duration> {
+        if ! self.contains(minute) {
+            return None;
+        }
+        let mut n = 0;
+        while self.contains(minute + n) {
+            n += 1;
+            if n > WEEK {
+                return Some(Duration::MAX);
+            }
+        }
+        Some(Duration::from_secs((n * 60 - second) as u64))
+    }
+
+    /// how long to wait for the next window, zero if inside a window now. (None if no window at all)
+    pub fn wait(&self, (minute, second): (u32, u32)) -> Option<Duration> {
+        let mut n = 0;
+        while ! self.contains(minute + n) {
+            n += 1;
+            if n > WEEK {
+                return None;
+            }
+        }
+        if n == 0 {
+            return Some(Duration::ZERO);
+        }
+        Some(Duration::from_secs((n * 60 - second) as u64))
+    }
+}
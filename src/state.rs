@@ -1,6 +1,7 @@
-use std::sync::Arc;
-use std::time::{SystemTime, Duration};
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 
 use anyhow::Context;
 use melwallet_client::WalletClient;
@@ -9,21 +10,29 @@ use stdcode::StdcodeSerializeExt;
 use themelio_nodeprot::ValClient;
 use themelio_stf::Tip910MelPowHash;
 use themelio_structs::{
+    BlockHeight,
     CoinData, CoinDataHeight, CoinID,
     CoinValue, Denom,
-    NetID,
+    Header,
     PoolKey, TxHash, TxKind,
     Address,
 };
 
-use crate::{repeat_fallible, panic_exit, new_void_address, new_null_dst};
+use tmelcrypt::HashVal;
+
+use crate::{repeat_fallible, panic_exit, new_null_dst};
+use crate::backend::{Chain, NodeChain};
+use crate::db::{self, TrySendProof, SeedRecord, SeedState};
+use crate::control::MintControl;
 
 #[derive(Clone)]
 pub struct MintState {
-    wallet: WalletState, // wrapped wallet add more function (dual-unlock, mel-only-or-0, )
+    chain: Arc<dyn Chain>, // the mint wallet (wrapped wallet add more function: dual-unlock, mel-only-or-0, ) and the blockchain
     client: ValClient, // connect for a blockchain node
     pub fee_handler: FeeSchedule,
     pub seed_handler: SeedSchedule,
+    affinity: Option<Vec<usize>>, // pin mint thread N to the CPU affinity[N % len]
+    pub control: Arc<MintControl>, // pause the mint threads
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,10 +42,18 @@ struct PrepareReq {
 }
 
 impl MintState {
-    pub fn new(wallet: WalletClient, client: ValClient, fee: FeeSchedule) -> Self {
-        let ws = WalletState (wallet);
+    pub fn new(wallet: WalletClient, password: Option<WalletPassword>, client: ValClient, fee: FeeSchedule) -> Self {
+        let chain = NodeChain {
+            wallet: WalletState (wallet, password, Arc::new(smol::lock::Mutex::new(()))),
+            client: client.clone(),
+        };
+        Self::with_chain(Arc::new(chain), client, fee)
+    }
+
+    /// the seeds and mint threads use `chain` (instead of the wallet and the node of `client`)
+    pub fn with_chain(chain: Arc<dyn Chain>, client: ValClient, fee: FeeSchedule) -> Self {
         Self {
-            wallet: ws.clone(),
+            chain: chain.clone(),
             client,
             fee_handler: fee/*FeeSchedule {
                 history: vec![],
@@ -45,90 +62,106 @@ impl MintState {
                 quit: false,
             }*/,
             seed_handler: SeedSchedule {
-                wallet: ws,
+                chain,
                 ttl: None,
+                ttl_override: None,
                 expired: HashMap::new(),
-                covnull: Some(new_null_dst()),
+                expired_mode: ExpiredSeeds::Keep,
+                sweep_dry_run: false,
+                covnull: None,
                 send_bulk: false,
+                in_use: Arc::new(Mutex::new(HashSet::new())),
             },
+            affinity: None,
+            control: Arc::new(MintControl::default()),
         }
     }
 
-    /// Creates a partially-filled-in transaction, with the given difficulty, that's neither signed nor feed. The caller should fill in the DOSC output.
+    pub fn set_affinity(&mut self, cpus: Option<Vec<usize>>) {
+        self.affinity = cpus.filter(|v| ! v.is_empty());
+    }
+
+    /// Creates a partially-filled-in transaction, with the given difficulty of each thread, that's neither signed nor feed. The caller should fill in the DOSC output.
+    /// `on_start` is called once the seeds are selected (so the seeds of next round can be generated while minting, without them),
+    /// each proof is given to `on_proof` as soon as its thread finished. (if cancelled by the control, only the finished proofs are returned)
     pub async fn mint_batch(
         &self,
-        difficulty: usize,
+        difficulties: &[usize],
+        on_start: impl FnOnce(),
         on_progress: impl Fn(usize, f64) + Sync + Send + 'static,
+        on_proof: impl Fn(&TrySendProof) + Sync + Send,
         threads: usize,
-    ) -> surf::Result<Vec<(CoinID, CoinDataHeight, Vec<u8>)>> {
+    ) -> surf::Result<Vec<TrySendProof>> {
         //#[cfg(not(target_os="android"))]
         //use thread_priority::{ set_current_thread_priority, ThreadPriority };
         use thread_priority::*;
 
-        // limit the thread count to less-than or equal to 255
-        // because CoinID.index just unsigned 8-bit integer.
-        assert!(threads <= 0xff);
-
         // we do not need to save the expired seeds, so just clone
-        let curr_height = self.client.snapshot().await?.current_header().height.0;
+        let curr_height = self.chain.height().await?;
         let raw_seeds = self.seed_handler.clone().raw(curr_height).await?;
 
-        // convert non-bulk & bulk seeds to general format
-        let mut seeds: Vec<CoinID> = vec![];
-        let mut seeds_tx: Option<TxHash> = None;
-        for (id, val) in raw_seeds {
-            if let Some(tx) = seeds_tx {
-                if id.txhash != tx {
-                    continue; /* (simple ignore other seeds) */
-
-                    /* (or just panic)
-                    panic!("unexpected different txhash of seeds!");
-                    */
-                }
-            } else {
-                seeds_tx = Some(id.txhash);
-            }
+        if difficulties.len() < threads {
+            return Err(surf::Error::new(500, anyhow::Error::msg(format!("the difficulties of {} threads are not given: {:?}", threads, difficulties))));
+        }
 
-            if self.seed_handler.send_bulk {
-                if val.value == CoinValue(threads as u128) {
-                    for n in 0..threads {
-                        let mut id = id.clone();
-                        id.index = n as u8;
-                        seeds.push(id);
-                    }
-                }
-            } else {
-                seeds.push(id);
-            }
+        // only the unit seeds can be used (the bulk seeds are split by SeedSchedule::generate)
+        let selected = select_seeds(&raw_seeds, threads);
+        if selected.units.len() < threads {
+            return Err(surf::Error::new(500, anyhow::Error::msg(format!("not enough seeds for {} threads: {:?}", threads, selected))));
+        }
+        let seeds: Vec<CoinID> = selected.units;
+        // the seed txs that still have unused seeds after this round (the leftover is used by later rounds)
+        let leftover_txs: HashSet<TxHash> = raw_seeds.keys()
+            .filter(|id| ! seeds.contains(id))
+            .map(|id| id.txhash)
+            .collect();
+        // never selected by others until spent (or this round ended without its proof)
+        let mut reserved = self.seed_handler.reserve(&seeds);
+        on_start();
+
+        // a stop requested before the threads started: never start them
+        if self.control.is_stopped() {
+            log::info!("stop requested, not starting the mint threads");
+            return Ok(vec![]);
         }
 
-        // finialize the tx hash of seeds.
-        let seeds_tx: TxHash = if let Some(h) = seeds_tx { h } else {
-            panic!("Failed to get a seed tx!!");
-        };
-        // make sure we have seeds to minting or panic
-        assert!(seeds.len() >= threads);
+        // the seeds may come from different seed txs
+        // (more than MAX_TX_OUTPUTS threads always needs multiple seed txs)
+        let mut seeds_txs: Vec<TxHash> = seeds.iter().map(|id| id.txhash).collect();
+        seeds_txs.sort_by_key(|h| h.0.0);
+        seeds_txs.dedup();
 
-        log::info!("Starting mint for TX {}", seeds_tx);
+        log::info!("Starting mint for TX {:?}", seeds_txs);
+        for h in &seeds_txs {
+            if let Err(e) = db::set_seed_state(*h, SeedState::InUse) {
+                log::warn!("cannot update the seed record {}: {:?}", h, e);
+            }
+        }
         let on_progress = Arc::new(on_progress);
-        let mut proof_thrs = Vec::new();
+        // each thread sends its index when finished
+        let (send_done, recv_done) = smol::channel::unbounded::<usize>();
+        let mut proof_thrs = HashMap::new();
         for (idx, seed) in seeds.iter().copied().take(threads).enumerate() {
-            let tip_cdh = repeat_fallible(|| async { self.client.snapshot().await?.get_coin(seed).await })
+            log::debug!("thread {} uses seed {:?}", idx, seed);
+            let tip_cdh = repeat_fallible(|| self.chain.get_coin(seed))
                 .await.context("transaction's input spent from behind our back")?;
 
             log::debug!("tip_cdh = {:#?}", tip_cdh);
 
-            let snapshot = self.client.snapshot().await?;
-            log::debug!("snapshot header = {:?}", snapshot.current_header());
-
-            let tip_header_hash = repeat_fallible(|| snapshot.get_older(tip_cdh.height))
-                .await
-                .current_header()
-                .hash();
+            let tip_header_hash = repeat_fallible(|| self.chain.header_hash(tip_cdh.height)).await;
             let chi = tmelcrypt::hash_keyed(&tip_header_hash, &seed.stdcode());
             let on_progress = on_progress.clone();
+            let cpu: Option<usize> = self.affinity.as_ref().map(|v| v[idx % v.len()]);
+            let control = self.control.clone();
+            let difficulty = difficulties[idx];
+            let send_done = send_done.clone();
 
             let proof_fut = std::thread::Builder::new().name(format!("Mint-{}", idx)).spawn(move || {
+                if let Some(cpu) = cpu {
+                    crate::affinity::pin_current(cpu);
+                    log::debug!("thread {} pinned to CPU {}", idx, cpu);
+                }
+
                 // try set min "nice value" for all mint threads.
                 if ThreadPriority::Min.set_for_current().is_err() {
                     #[cfg(not(target_os="windows"))]
@@ -158,46 +191,147 @@ impl MintState {
                 }
 
                 // core function of melpow
-                (
-                    tip_cdh,
-                    melpow::Proof::generate_with_progress(
-                        &chi,
-                        difficulty,
-                        |progress| {
-                            if fastrand::f64() < 0.1 {
-                                on_progress(idx, progress)
-                            }
-                        },
-                        Tip910MelPowHash,
-                    ),
-                )
+                let started = Instant::now();
+                let proof = control.run(|| melpow::Proof::generate_with_progress(
+                    &chi,
+                    difficulty,
+                    |progress| {
+                        // blocks here if paused, or stops here if cancelled
+                        control.checkpoint(idx);
+                        if fastrand::f64() < 0.1 {
+                            on_progress(idx, progress)
+                        }
+                    },
+                    Tip910MelPowHash,
+                ));
+                let out = proof.map(|p| (tip_cdh, p, started.elapsed()));
+                let _ = send_done.try_send(idx);
+                out
             })?;
 
-            proof_thrs.push(proof_fut);
+            proof_thrs.insert(idx, (seed, chi, proof_fut));
         }
+        std::mem::drop(send_done);
+
+        // stop the thread if its seed spent from behind our back (its proof is useless)
+        let _spent_task = {
+            let chain = self.chain.clone();
+            let control = self.control.clone();
+            let seeds = seeds.clone();
+            smol::spawn(async move {
+                loop {
+                    smol::Timer::after(Duration::from_secs(120)).await;
+                    for (idx, seed) in seeds.iter().take(threads).enumerate() {
+                        if let Ok(None) = chain.get_coin(*seed).await {
+                            if ! control.is_thread_cancelled(idx) {
+                                log::warn!("the seed {:?} of thread {} spent from behind our back, cancel the thread", seed, idx);
+                                control.cancel_thread(idx);
+                            }
+                        }
+                    }
+                }
+            })
+        };
 
         let mut out = vec![];
-        for (seed, proof) in seeds.into_iter().zip(proof_thrs.into_iter()) {
-            let result = smol::unblock(move || proof.join().unwrap()).await;
-            out.push((seed, result.0, result.1.to_bytes()))
+        // the seed txs of cancelled threads still have unused seeds
+        let mut unfinished_txs: Vec<TxHash> = vec![];
+        // in the order of finish, so a proof does not wait for the slower threads
+        while let Ok(idx) = recv_done.recv().await {
+            let (seed, chi, proof) = proof_thrs.remove(&idx).unwrap();
+            let result = match smol::unblock(move || proof.join().unwrap()).await {
+                Some(r) => r,
+                None => {
+                    log::info!("mint thread {} cancelled", idx);
+                    unfinished_txs.push(seed.txhash);
+                    continue;
+                }
+            };
+            let trys = TrySendProof {
+                coin: seed,
+                data: result.0,
+                proof: result.1.to_bytes(),
+                difficulty: difficulties[idx],
+                chi,
+                thread: idx,
+                elapsed: result.2,
+            };
+            // the seed will be spent by the DoscMint tx of its proof
+            reserved.seeds.retain(|id| *id != seed);
+            on_proof(&trys);
+            out.push(trys);
+        }
+        // a thread exited without finish (panicked)
+        for (_, (_, _, proof)) in proof_thrs {
+            smol::unblock(move || proof.join().unwrap()).await;
+        }
+        out.sort_by_key(|trys| trys.thread);
+        for h in &seeds_txs {
+            // proved only if all its seeds are used, otherwise the rest is still usable
+            let state = if unfinished_txs.contains(h) || leftover_txs.contains(h) { SeedState::Fresh } else { SeedState::Proved };
+            if let Err(e) = db::set_seed_state(*h, state) {
+                log::warn!("cannot update the seed record {}: {:?}", h, e);
+            }
         }
         Ok(out)
     }
 
-    /// Sends a transaction.
+    // the chi (puzzle of melpow) of a seed: keyed hash of the seed by the header hash of the block where seed located.
+    async fn seed_chi(&self, seed: CoinID, height: BlockHeight) -> surf::Result<HashVal> {
+        let tip_header_hash = self.chain.header_hash(height).await?;
+        Ok( tmelcrypt::hash_keyed(&tip_header_hash, &seed.stdcode()) )
+    }
+
+    /// Finds the difficulty of a proof which stored without it (by older versions), by trying to verify it with each possible difficulty.
+    /// returns the difficulty and chi if found one, or None if the proof cannot pass verification in any case.
+    pub async fn recover_difficulty(
+        &self,
+        seed: CoinID,
+        data: &CoinDataHeight,
+        proof: &[u8],
+    ) -> surf::Result<Option<(usize, HashVal)>> {
+        let proof = if let Some(p) = melpow::Proof::from_bytes(proof) { p } else {
+            return Ok(None);
+        };
+        let chi = self.seed_chi(seed, data.height).await?;
+
+        let found = smol::unblock(move || {
+            (1..=64).find(|difficulty| proof.verify(&chi, *difficulty, Tip910MelPowHash))
+        }).await;
+        Ok( found.map(|difficulty| (difficulty, chi)) )
+    }
+
+    /// Verifies a proof locally, by recompute the chi from seed and the block header of seed located. returns false if the proof is invalid.
+    pub async fn verify_proof(&self, trys: &TrySendProof) -> surf::Result<bool> {
+        let proof = if let Some(p) = melpow::Proof::from_bytes(&trys.proof) { p } else {
+            log::warn!("cannot decode proof bytes (corrupt or truncated) of seed {:?}", trys.coin);
+            return Ok(false);
+        };
+
+        let chi = self.seed_chi(trys.coin, trys.data.height).await?;
+        if chi != trys.chi {
+            log::warn!("mismatched chi of seed {:?}: stored {:?} but recomputed {:?}", trys.coin, trys.chi, chi);
+            return Ok(false);
+        }
+
+        let difficulty = trys.difficulty;
+        Ok( smol::unblock(move || proof.verify(&chi, difficulty, Tip910MelPowHash)).await )
+    }
+
+    /// Sends a transaction. (it does not change the state, so it can be sent while minting: the caller should push the fee record to fee_handler.history)
     pub async fn send_mint_transaction(
-        &mut self,
+        &self,
         seed: CoinID,
         difficulty: usize,
         proof: Vec<u8>,
         ergs: CoinValue,
-    ) -> surf::Result<TxHash> {
-        self.wallet.unlock().await?;
+    ) -> surf::Result<(TxHash, FeeRecord)> {
+        let own_cov = self.chain.address().await?;
+        let is_testnet = self.chain.is_testnet().await?;
+        let mels = self.erg_to_mel(ergs).await?;
 
-        let summary = self.wallet.0.summary().await?;
-        let own_cov = summary.address;
-        let is_testnet = summary.network != NetID::Mainnet;
-        let tx = self.wallet.0.prepare_transaction(
+        let max_fee = if (!is_testnet) && (!self.fee_handler.allow_any_tx) { Some(mels) } else { None };
+        let (txhash, fees) = self.chain.send(
                 TxKind::DoscMint,
                 vec![seed],
                 vec![CoinData {
@@ -206,32 +340,24 @@ impl MintState {
                     additional_data: vec![],
                     covhash: own_cov,
                 }],
-                vec![],
                 (difficulty, proof).stdcode(),
                 vec![Denom::Erg],
+                max_fee,
             )
             .await?;
-
-        let fees = tx.fee;
-        let mels = self.erg_to_mel(ergs).await?;
         if fees >= mels {
             log::warn!("WARNING: This doscMint fee({} MEL) great-than-or-equal to approx-income({} MEL) amount!! you should check your difficulty or a network issue.", fees, mels);
-            if fees > mels && (!is_testnet) && (!self.fee_handler.allow_any_tx) {
-                return Err(surf::Error::new(403, anyhow::Error::msg("refused to send any high-fee tx.")));
-            }
         }
-
-        let txhash = self.wallet.0.send_tx(tx).await?;
         log::debug!("(fee-safe) sent DoscMint tx with fee: {}", fees);
 
-        self.fee_handler.history.push(FeeRecord{
+        let rec = FeeRecord{
             kind: TxKind::DoscMint,
             time: SystemTime::now(),
-            balance: self.wallet.get_balance().await?,
+            balance: self.chain.balance().await?,
             fee: fees,
             income: mels,
-        });
-        Ok(txhash)
+        };
+        Ok((txhash, rec))
     }
 
     // /// Sends a transaction out. What this actually does is to re-prepare another transaction with the same inputs, outputs, and data, so that the wallet can sign it properly.
@@ -252,15 +378,35 @@ impl MintState {
     //     Ok(())
     // }
 
-    /// Converts a given number of doscs to mel.
-    pub async fn convert_doscs(&mut self, doscs: CoinValue) -> surf::Result<()> {
-        self.wallet.unlock().await?;
+    /// Estimates the fee of a DoscMint tx of `difficulty` (before any sent), by signing a tx of same size but never sending it.
+    pub async fn estimate_mint_fee(&self, difficulty: usize) -> surf::Result<CoinValue> {
+        let own_cov = self.chain.address().await?;
+        let header = self.client.snapshot().await?.current_header();
+        let ergs = CoinValue(proof_reward(&header, difficulty, 0));
+        let proof = vec![0u8; smol::unblock(move || proof_size(difficulty)).await];
+        self.chain.prepare_fee(
+                TxKind::DoscMint,
+                vec![],
+                vec![CoinData {
+                    denom: Denom::Erg,
+                    value: ergs,
+                    additional_data: vec![],
+                    covhash: own_cov,
+                }],
+                (difficulty, proof).stdcode(),
+                vec![Denom::Erg],
+            )
+            .await
+    }
 
-        let summary = self.wallet.0.summary().await?;
-        let my_address = summary.address;
-        let is_testnet = summary.network != NetID::Mainnet;
+    /// Converts a given number of doscs to mel. (like send_mint_transaction, the caller should push the fee record)
+    pub async fn convert_doscs(&self, doscs: CoinValue) -> surf::Result<FeeRecord> {
+        let my_address = self.chain.address().await?;
+        let is_testnet = self.chain.is_testnet().await?;
+        let mels = self.erg_to_mel(doscs).await?;
 
-        let tx = self.wallet.0.prepare_transaction(
+        let max_fee = if (!is_testnet) && self.fee_handler.allow_any_tx { Some(mels) } else { None };
+        let (txhash, fees) = self.chain.send(
                 TxKind::Swap,
                 vec![],
                 vec![CoinData {
@@ -269,34 +415,45 @@ impl MintState {
                     denom: Denom::Erg,
                     additional_data: vec![],
                 }],
-                vec![],
                 PoolKey::new(Denom::Mel, Denom::Erg).to_bytes(),
                 vec![],
+                max_fee,
             )
             .await?;
-
-        let fees = tx.fee;
-        let mels = self.erg_to_mel(doscs).await?;
         if fees >= mels {
             log::warn!("WARNING: This ERG-to-MEL swap fee({} MEL) great-than-or-equal to income({} MEL) amount! you should check your difficulty or a network issue.", fees, mels);
-            if fees > mels && (!is_testnet) && self.fee_handler.allow_any_tx {
-                return Err(surf::Error::new(403, anyhow::Error::msg("refused to send any high-fee tx.")));
-            }
         }
 
-        let txhash = self.wallet.0.send_tx(tx).await?;
-
         log::debug!("(fee-safe) sent ERG-to-MEL swap tx with fee: {}", fees);
-        self.fee_handler.history.push(FeeRecord{
+        let rec = FeeRecord{
             kind: TxKind::Swap,
             time: SystemTime::now(),
-            balance: self.wallet.get_balance().await?,
+            balance: self.chain.balance().await?,
             fee: fees,
             income: mels,
-        });
+        };
 
-        self.wallet.0.wait_transaction(txhash).await?;
-        Ok(())
+        self.chain.wait(txhash).await?;
+        Ok(rec)
+    }
+
+    /// Sends some MEL to the payout address
+    pub async fn send_payout(&self, payout: Address, value: CoinValue) -> surf::Result<TxHash> {
+        let (txhash, _) = self.chain.send(
+                TxKind::Normal,
+                vec![],
+                vec![CoinData {
+                    covhash: payout,
+                    value,
+                    additional_data: vec![],
+                    denom: Denom::Mel,
+                }],
+                vec![],
+                vec![],
+                None,
+            )
+            .await?;
+        Ok(txhash)
     }
 
     /// Converts ERG to MEL
@@ -310,74 +467,272 @@ impl MintState {
             .expect("no erg/mel pool");
         Ok(pool.swap_many(ergs.0, 0).1.into())
     }
+
+    /// Finds the max age (blocks) of a seed, that the reward of its proof still covers the fee. None if even a fresh seed cannot.
+    pub async fn break_even_age(&self, difficulty: usize, fee: CoinValue) -> surf::Result<Option<u64>> {
+        let snapshot = self.client.snapshot().await?;
+        let header = snapshot.current_header();
+        let pool = snapshot
+            .get_pool(PoolKey::mel_and(Denom::Erg))
+            .await?
+            .expect("no erg/mel pool");
+        let mels_at = |age: u64| -> CoinValue {
+            pool.clone().swap_many(proof_reward(&header, difficulty, age), 0).1.into()
+        };
+
+        if mels_at(0) < fee {
+            return Ok(None);
+        }
+        if mels_at(MAX_SEED_TTL) >= fee {
+            return Ok(Some(MAX_SEED_TTL));
+        }
+        // the reward only decreases by age
+        let (mut lo, mut hi) = (0, MAX_SEED_TTL);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if mels_at(mid) >= fee {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(Some(lo))
+    }
+}
+
+/// Whether a coin is a seed: the output of new-coin tx itself, or the split outputs of a bulk seed (the consolidated expired seeds are not)
+pub fn is_seed(id: &CoinID, data: &CoinData, records: &HashMap<TxHash, SeedRecord>) -> bool {
+    if let Denom::Custom(denom) = data.denom {
+        if denom == id.txhash {
+            return true;
+        }
+        if let Some(parent) = records.get(&denom) {
+            return parent.state == SeedState::Split(id.txhash);
+        }
+    }
+    false
+}
+
+/// How to get the seeds for mint threads, from the seed coins of wallet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeedSelection {
+    /// the unit seeds to use (one per thread)
+    pub units: Vec<CoinID>,
+    /// the bulk seeds should be split for the rest threads
+    pub split: Vec<(CoinID, CoinData)>,
+    /// how many seeds still missing (needs to generate)
+    pub missing: usize,
+}
+
+/// Selects seeds for `threads` mint threads: unit seeds first, then split the bulk seeds as few as possible.
+/// (any leftover seeds are kept for later rounds, so changing thread count never strands them)
+pub fn select_seeds(seeds: &HashMap<CoinID, CoinData>, threads: usize) -> SeedSelection {
+    let mut units: Vec<CoinID> = vec![];
+    let mut bulks: Vec<(CoinID, CoinData)> = vec![];
+    for (id, data) in seeds {
+        if data.value == CoinValue(1) {
+            units.push(*id);
+        } else if data.value > CoinValue(1) && data.value.0 <= MAX_TX_OUTPUTS as u128 {
+            bulks.push((*id, data.clone()));
+        }
+    }
+    // the order of HashMap is random, sort them to make the selection stable
+    units.sort_by_key(|id| (id.txhash.0.0, id.index));
+    bulks.sort_by_key(|(id, data)| (data.value, id.txhash.0.0, id.index));
+    units.truncate(threads);
+
+    let mut rest = threads - units.len();
+    let mut split = vec![];
+    if rest > 0 {
+        // the smallest bulk seed that covers all the rest, otherwise the largest ones
+        if let Some(i) = bulks.iter().position(|(_, data)| data.value.0 >= rest as u128) {
+            split.push(bulks.remove(i));
+            rest = 0;
+        } else {
+            while rest > 0 {
+                if let Some(b) = bulks.pop() {
+                    rest = rest.saturating_sub(b.1.value.0 as usize);
+                    split.push(b);
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    SeedSelection {
+        units,
+        split,
+        missing: rest,
+    }
+}
+
+/// The reward (ERG) of a proof, if the seed is `age` blocks old when submitting it.
+pub fn proof_reward(header: &Header, difficulty: usize, age: u64) -> u128 {
+    let reward_speed = 2u128.pow(difficulty as u32) / (age + 40) as u128;
+    let reward = themelio_stf::calculate_reward(reward_speed * 100, header.dosc_speed, difficulty as u32, true);
+    themelio_stf::dosc_to_erg(header.height, reward)
+}
+
+/// The size (bytes) of a melpow proof of `difficulty`, extrapolated from two small proofs (the size grows linearly by difficulty).
+pub fn proof_size(difficulty: usize) -> usize {
+    let size = |d: usize| melpow::Proof::generate(&[0u8; 32], d, Tip910MelPowHash).to_bytes().len() as f64;
+    let (lo, hi) = (8, 12);
+    let (s_lo, s_hi) = (size(lo), size(hi));
+    (s_lo + (s_hi - s_lo) / (hi - lo) as f64 * (difficulty as f64 - lo as f64)).max(0.0) as usize
+}
+
+// max outputs of a seed tx (CoinID.index just unsigned 8-bit integer)
+pub const MAX_TX_OUTPUTS: usize = 0xff;
+
+// the range of seed TTL (blocks)
+pub const MIN_SEED_TTL: u64 = 10;
+pub const MAX_SEED_TTL: u64 = 3600 * 12 / 30;
+
+/// What to do with the expired seeds (they are unspent new-coin tokens of no use, only takes the coin list of wallet)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExpiredSeeds {
+    Keep, // leave them in mint wallet
+    Consolidate, // merge them back to mint wallet (one coin per seed tx)
+    Burn, // send them to a null address
+}
+impl std::str::FromStr for ExpiredSeeds {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "consolidate" => Ok(Self::Consolidate),
+            "burn" => Ok(Self::Burn),
+            _ => Err(format!("unknown expired seeds handling {:?}, should be keep/consolidate/burn", s)),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct SeedSchedule {
     /// expire blocks of each seeds, all expired coins will be ignored
     pub ttl: Option<u64>,
+    /// fixed TTL given by user, never recompute it
+    pub ttl_override: Option<u64>,
     /// here store all expired seeds
     pub expired: HashMap<TxHash, Vec<(CoinID, CoinData)>>,
-    /// what address to receive expired seeds
+    /// what to do with expired seeds
+    pub expired_mode: ExpiredSeeds,
+    /// only preview the sweep of expired seeds (never send)
+    pub sweep_dry_run: bool,
+    /// the null address to receive burned seeds (generated once needed)
     pub covnull: Option<Address>,
-    /// wallet for seeds only
-    pub wallet: WalletState,
+    /// the wallet and blockchain of seeds
+    pub chain: Arc<dyn Chain>,
     /// bulk send
     pub send_bulk: bool,
+    /// the seeds of running mint threads (and of the proofs not submitted yet), never selected again
+    pub in_use: Arc<Mutex<HashSet<CoinID>>>,
 }
+
+// the seeds reserved by a round, released when dropped (except the seeds of finished proofs)
+struct SeedsInUse {
+    in_use: Arc<Mutex<HashSet<CoinID>>>,
+    seeds: Vec<CoinID>,
+}
+impl Drop for SeedsInUse {
+    fn drop(&mut self) {
+        let mut in_use = self.in_use.lock().unwrap();
+        for id in &self.seeds {
+            in_use.remove(id);
+        }
+    }
+}
+
 impl SeedSchedule {
     pub fn bulk(&mut self) {
         self.send_bulk = true;
     }
 
-    /// caller provides Duration; method returns **current** TTL value.
-    pub fn set_expire(&mut self, lifetime: Duration) -> u64 {
-        if let Some(blocks) = self.ttl {
-            return blocks;
+    pub fn expired_handling(&mut self, mode: ExpiredSeeds, dry_run: bool) {
+        self.expired_mode = mode;
+        self.sweep_dry_run = dry_run;
+    }
+
+    // the destination of expired seeds, None if keep them.
+    fn sweep_dst(&mut self, my_address: Address) -> Option<Address> {
+        match self.expired_mode {
+            ExpiredSeeds::Keep => None,
+            ExpiredSeeds::Consolidate => Some(my_address),
+            ExpiredSeeds::Burn => {
+                if self.covnull.is_none() {
+                    self.covnull = Some(new_null_dst());
+                }
+                self.covnull
+            },
         }
+    }
 
-        let min = 3600 * 3;
-        let max = 3600 * 12;
+    // reserves the seeds for a round
+    fn reserve(&self, seeds: &[CoinID]) -> SeedsInUse {
+        self.in_use.lock().unwrap().extend(seeds.iter().copied());
+        SeedsInUse {
+            in_use: self.in_use.clone(),
+            seeds: seeds.to_vec(),
+        }
+    }
 
-        let mut secs = lifetime.as_secs();
-        if secs < min { secs = min; }
-        if secs > max { secs = max; }
+    pub fn ttl_override(&mut self, blocks: Option<u64>) {
+        self.ttl_override = blocks;
+    }
+
+    /// caller provides the break-even age of seed (see MintState::break_even_age, None if the fee is unknown yet) and the estimated round time;
+    /// method returns **current** TTL value. (it should be re-computed every round, because the difficulty may changes)
+    pub fn set_expire(&mut self, break_even: Option<u64>, round: Duration) -> u64 {
+        if let Some(blocks) = self.ttl_override {
+            self.ttl = Some(blocks);
+            return blocks;
+        }
 
         // ttl-blocks = expire-time / block-interval (all time units seconds)
-        let blocks = secs / 30;
+        let round_blocks = (round.as_secs() + 29) / 30;
+        let blocks = match break_even {
+            // the seed still needs a round for minting before its proof submitted
+            Some(age) => age.saturating_sub(round_blocks),
+            // no DoscMint fee known yet (or unprofitable even fresh): 2x of round time (at least 3 hours)
+            None => (round_blocks * 2).max(3600 * 3 / 30),
+        };
+        let blocks = blocks.clamp(MIN_SEED_TTL, MAX_SEED_TTL);
 
         self.ttl = Some(blocks);
         return blocks;
     }
 
     /// Generates a list of "seed" coins.
-    pub async fn generate(&mut self, client: ValClient, threads: usize, fee_handler: &mut FeeSchedule) -> surf::Result<()> {
-        self.wallet.unlock().await?;
+    pub async fn generate(&mut self, threads: usize, fee_handler: &mut FeeSchedule) -> surf::Result<()> {
         let bulk = self.send_bulk;
 
-        let my_address = self.wallet.0.summary().await?.address;
+        let my_address = self.chain.address().await?;
         loop {
-            let seedmap = self.raw(client.snapshot().await?.current_header().height.0).await?;
-            if bulk {
-                for (_, data) in seedmap {
-                    if data.value == CoinValue(threads as u128) {
-                        return Ok(());
-                    }
-                }
-            } else {
-                // normal non-bulk
-                if seedmap.len() >= threads {
-                    return Ok(());
+            let seedmap = self.raw(self.chain.height().await?).await?;
+            let selected = select_seeds(&seedmap, threads);
+            if selected.missing == 0 && selected.split.is_empty() {
+                return Ok(());
+            }
+
+            // the bulk seeds must be split to unit seeds before minting (each mint thread spends a whole coin)
+            if ! selected.split.is_empty() {
+                for (id, data) in selected.split {
+                    self.split(id, data, my_address, fee_handler).await?;
                 }
+                continue;
             }
 
-            // generate a bunch of custom-token utxos
+            // generate a bunch of custom-token utxos (only the missing, any leftover seeds will be used)
+            // a tx has at most MAX_TX_OUTPUTS outputs (CoinID.index just unsigned 8-bit integer), so too many threads needs multiple seed txs (the loop continues until no missing).
+            let missing = selected.missing;
             let mut outputs: Vec<CoinData> = if bulk {
+                // also a bulk seed must be split in one tx, and only one bulk seed per tx (the split seeds are recognized by the state of seed tx)
                 vec![
                     CoinData {
                         covhash: my_address,
                         denom: Denom::NewCoin,
-                        value: CoinValue( threads as u128 ),
+                        value: CoinValue( missing.min(MAX_TX_OUTPUTS) as u128 ),
                         additional_data: vec![],
                     }
                 ]
@@ -387,85 +742,236 @@ impl SeedSchedule {
                     denom: Denom::NewCoin,
                     value: CoinValue(1),
                     additional_data: vec![],
-                }).take(threads).collect()
+                }).take(missing.min(MAX_TX_OUTPUTS)).collect()
             };
+            let seed_outputs = outputs.len();
 
-            let mut exp_add: usize = 0;
-            if self.expired.len() > (threads*15) { // sweep all expired seeds (always bulk)
-                let exp_dst = if let Some(d) = self.covnull { d } else { new_void_address() };
+            // sweep all expired seeds (always one output per seed tx), if there are too many of them.
+            let mut swept: Vec<(TxHash, CoinValue)> = vec![];
+            let sweep_dst = if self.expired.len() > (threads*15) { self.sweep_dst(my_address) } else { None };
+            if let Some(exp_dst) = sweep_dst {
+                let mut sweep_outputs = vec![];
                 for (exp_th, exp_vals) in &self.expired {
+                    // the rest will be swept by next tx
+                    if seed_outputs + sweep_outputs.len() >= MAX_TX_OUTPUTS {
+                        break;
+                    }
                     log::debug!("sweep all expired new-coin(s): tx-hash={:?}, values={:?}", exp_th, exp_vals);
                     assert!( exp_vals.len() > 0 );
-                    let (_exp_id, exp_data) = exp_vals[0].clone();
-
-                    let denom = exp_data.denom;
+                    // the seeds of split tx are the denom of its bulk seed
+                    let denom = exp_vals[0].1.denom;
                     for it in exp_vals {
                         assert!( it.1.denom == denom );
                     }
 
-                    outputs.push(CoinData {
-                        covhash: if (fastrand::u128(..) % 2) == 0 { exp_dst } else { "t1m9v0fhkbr7q1sfg59prke1sbpt0gm2qgrb166mp8n8m59962gdm0".parse()? },
+                    // bulk seed is a single coin with value of thread count
+                    let value: CoinValue = exp_vals.iter().map(|it| it.1.value).fold(CoinValue(0), |a, b| a + b);
+                    swept.push((*exp_th, value));
+                    sweep_outputs.push(CoinData {
+                        covhash: exp_dst,
                         denom,
-                        value: CoinValue( exp_vals.len() as u128 ),
+                        value,
                         additional_data: vec![],
                     });
-                    exp_add += 1;
+                }
+
+                if self.sweep_dry_run {
+                    log::info!("[dry-run] would sweep {} expired seed tx(s) to {} ({:?}): {:?}", swept.len(), exp_dst, self.expired_mode, swept);
+                    swept.clear();
+                } else {
+                    outputs.extend(sweep_outputs);
                 }
             }
 
-            // prepare tx...
-            let tx = self.wallet.0.prepare_transaction(
+            // prepare and send tx...
+            let (sent_hash, fees) = self.chain.send(
                 TxKind::Normal,
                 vec![],
                 outputs,
                 vec![],
                 vec![],
-                vec![],
+                None,
             ).await?;
 
-            let fees = tx.fee;
-            let sent_hash = self.wallet.0.send_tx(tx).await?;
-
-            log::info!("added {} expired seeds to seed UTXO.", exp_add);
-            if exp_add > 0 {
-                self.expired.clear();
+            if let (Some(dst), false) = (sweep_dst, swept.is_empty()) {
+                log::info!("swept {} expired seed tx(s) to {} ({:?}).", swept.len(), dst, self.expired_mode);
+                for (th, _) in &swept {
+                    if let Err(e) = db::set_seed_state(*th, SeedState::Swept(sent_hash)) {
+                        log::warn!("cannot update the seed record {}: {:?}", th, e);
+                    }
+                }
+                let rec = db::SeedSweep {
+                    time: SystemTime::now(),
+                    txhash: sent_hash,
+                    dst,
+                    burned: self.expired_mode == ExpiredSeeds::Burn,
+                    seeds: swept,
+                    fee: fees,
+                };
+                if let Err(e) = db::write_sweep(&rec) {
+                    log::warn!("cannot record the sweep of expired seeds {:?}: {:?}", rec, e);
+                }
+                for (th, _) in &rec.seeds {
+                    self.expired.remove(th);
+                }
             }
 
             log::debug!("(fee-safe) sent newcoin tx with fee: {}", fees);
             fee_handler.history.push(FeeRecord{
                 kind: TxKind::Normal,
                 time: SystemTime::now(),
-                balance: self.wallet.get_balance().await?,
+                balance: self.chain.balance().await?,
                 fee: fees,
                 income: CoinValue(0),
             });
 
-            self.wallet.0.wait_transaction(sent_hash).await?;
+            let height = self.chain.wait(sent_hash).await?;
+
+            // record the new seeds, so never need to query its height again
+            let now = SystemTime::now();
+            let rec = SeedRecord {
+                txhash: sent_hash,
+                height,
+                threads: if bulk { missing.min(MAX_TX_OUTPUTS) } else { seed_outputs },
+                bulk,
+                ttl: self.ttl,
+                state: SeedState::Fresh,
+                created: now,
+                updated: now,
+            };
+            if let Err(e) = db::write_seed(&rec) {
+                log::warn!("cannot record the new seeds {:?}: {:?}", rec, e);
+            }
+        }
+    }
+
+    // Splits a bulk seed to unit seeds (with the same denom), the split tx is recorded as a seed tx.
+    async fn split(&mut self, id: CoinID, data: CoinData, my_address: Address, fee_handler: &mut FeeSchedule) -> surf::Result<()> {
+        let parent = if let Denom::Custom(h) = data.denom { h } else {
+            return Err(surf::Error::new(500, anyhow::Error::msg(format!("cannot split a non-seed coin {:?}", (id, data)))));
+        };
+        let count = data.value.0 as usize;
+        if count > MAX_TX_OUTPUTS {
+            return Err(surf::Error::new(500, anyhow::Error::msg(format!("cannot split a bulk seed {:?} of value {} in one tx (max {})", id, count, MAX_TX_OUTPUTS))));
+        }
+        let outputs: Vec<CoinData> = std::iter::repeat_with(|| CoinData {
+            covhash: my_address,
+            denom: data.denom,
+            value: CoinValue(1),
+            additional_data: vec![],
+        }).take(count).collect();
+
+        let (sent_hash, fees) = self.chain.send(
+            TxKind::Normal,
+            vec![id],
+            outputs,
+            vec![],
+            vec![],
+            None,
+        ).await?;
+        log::info!("split a bulk seed {:?} to {} seeds: tx-hash={}", id, count, sent_hash);
+
+        // the unit seeds are recognized by this state of the bulk seed
+        if let Err(e) = db::set_seed_state(parent, SeedState::Split(sent_hash)) {
+            log::warn!("cannot update the seed record {}: {:?}", parent, e);
+        }
+
+        log::debug!("(fee-safe) sent split tx with fee: {}", fees);
+        fee_handler.history.push(FeeRecord{
+            kind: TxKind::Normal,
+            time: SystemTime::now(),
+            balance: self.chain.balance().await?,
+            fee: fees,
+            income: CoinValue(0),
+        });
+
+        let height = self.chain.wait(sent_hash).await?;
+        let now = SystemTime::now();
+        let rec = SeedRecord {
+            txhash: sent_hash,
+            height,
+            threads: count,
+            bulk: false,
+            ttl: self.ttl,
+            state: SeedState::Fresh,
+            created: now,
+            updated: now,
+        };
+        if let Err(e) = db::write_seed(&rec) {
+            log::warn!("cannot record the split seeds {:?}: {:?}", rec, e);
         }
+        Ok(())
     }
 
     // caller needs provide current block number: self.height(num)
-    async fn raw(&mut self, height: u64) -> surf::Result<HashMap<CoinID, CoinData>> {
-        let unspent_coins = self.wallet.0.get_coins().await?;
+    pub(crate) async fn raw(&mut self, height: u64) -> surf::Result<HashMap<CoinID, CoinData>> {
+        let unspent_coins = self.chain.coins().await?;
+        // the spent seeds are no longer in use
+        self.in_use.lock().unwrap().retain(|id| unspent_coins.contains_key(id));
+        let in_use = self.in_use.lock().unwrap().clone();
 
         // valclient.snapshot().await?.current_header().height.0;
         let current_height = height;
 
+        // re-collect expired seeds on each check, otherwise they will be counted repeatedly
+        self.expired.clear();
+
+        // the height of each seed tx is stored in database
+        let mut records = match db::load_seeds() {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("cannot load seed records: {:?}", e);
+                HashMap::new()
+            }
+        };
+
         let mut seeds = HashMap::new();
         for (id, data) in unspent_coins {
-            if let Denom::Custom(_) = data.denom {
+            if in_use.contains(&id) {
+                continue;
+            }
+            if is_seed(&id, &data, &records) {
                 // if provides a TTL (unit: how many blocks), an expiration check will happen, it will ignore expired coins.
                 if let Some(ttl) = self.ttl {
-                    let coin_height = match self.wallet.0.wait_transaction(id.txhash).await {
-                        Ok(v) => v,
-                        Err(e) => {
-                            log::info!("cannot get seed height: {:?}", e);
-                            continue;
+                    let coin_height = if let Some(rec) = records.get(&id.txhash) {
+                        rec.height
+                    } else {
+                        // not recorded (created by older versions): query it once and record it
+                        let height = match self.chain.wait(id.txhash).await {
+                            Ok(v) => v,
+                            Err(e) => {
+                                log::info!("cannot get seed height: {:?}", e);
+                                continue;
+                            }
+                        };
+                        let now = SystemTime::now();
+                        let rec = SeedRecord {
+                            txhash: id.txhash,
+                            height,
+                            threads: 0,
+                            bulk: data.value > CoinValue(1),
+                            ttl: None,
+                            state: SeedState::Fresh,
+                            created: now,
+                            updated: now,
+                        };
+                        if let Err(e) = db::write_seed(&rec) {
+                            log::warn!("cannot record the seeds {:?}: {:?}", rec, e);
                         }
+                        records.insert(id.txhash, rec);
+                        height
                     };
                     if coin_height <= current_height {
                         if (current_height - coin_height) > ttl {
                             log::debug!("ignore too old seed: ttl={}, coin={:?}", ttl, (&id,&data));
+                            if let Some(rec) = records.get_mut(&id.txhash) {
+                                if rec.state != SeedState::Expired {
+                                    rec.state = SeedState::Expired;
+                                    if let Err(e) = db::set_seed_state(id.txhash, SeedState::Expired) {
+                                        log::warn!("cannot update the seed record {}: {:?}", id.txhash, e);
+                                    }
+                                }
+                            }
 
                             let th = id.txhash;
                             let v: &mut Vec<(CoinID, CoinData)> =
@@ -496,11 +1002,11 @@ impl SeedSchedule {
 
 #[derive(Copy, Clone, Debug)]
 pub struct FeeRecord {
-    kind: TxKind, // TxKind::Normal for newcoin; TxKind::DoscMint for doscMint; TxKind::Swap for swap.
-    time: SystemTime,
-    balance: CoinValue,
-    fee: CoinValue,
-    income: CoinValue, // ERG(should be convert and store MEL) for doscMint, or MEL for swap. any newcoin tx should be always 0.
+    pub(crate) kind: TxKind, // TxKind::Normal for newcoin; TxKind::DoscMint for doscMint; TxKind::Swap for swap.
+    pub(crate) time: SystemTime,
+    pub(crate) balance: CoinValue,
+    pub(crate) fee: CoinValue,
+    pub(crate) income: CoinValue, // ERG(should be convert and store MEL) for doscMint, or MEL for swap. any newcoin tx should be always 0.
 }
 #[derive(Clone, Debug)]
 pub struct FeeSchedule {
@@ -515,6 +1021,17 @@ pub struct FeeSchedule {
     pub quit: bool,
 }
 impl FeeSchedule {
+    /// the fee of last sent tx of this kind
+    pub fn last_fee(&self, kind: TxKind) -> Option<CoinValue> {
+        self.history.iter().rev().find(|it| it.kind == kind).map(|it| it.fee)
+    }
+
+    /// Adds fee records in time order: the settlement in background records its fees while the next round is minting.
+    pub fn record(&mut self, recs: impl IntoIterator<Item = FeeRecord>) {
+        self.history.extend(recs);
+        self.history.sort_by_key(|it| it.time);
+    }
+
     pub fn failsafe(&self) {
         let fh = self.history.clone();
         log::debug!("(fee-safe) our balance history: {:?}", fh);
@@ -532,7 +1049,7 @@ impl FeeSchedule {
 
             if i > 0 {
                 let prev = fh[i-1];
-                assert!( prev.time < it.time );
+                assert!( prev.time <= it.time );
             }
 
             // skip any newcoin tx(s)
@@ -578,7 +1095,16 @@ impl FeeSchedule {
 }
 
 #[derive(Clone, Debug)]
-pub struct WalletState(WalletClient);
+pub struct WalletState(pub(crate) WalletClient, pub(crate) Option<WalletPassword>, Arc<smol::lock::Mutex<()>>);
+
+/// the password of mint-wallet (never display it in debug output)
+#[derive(Clone)]
+pub struct WalletPassword(pub String);
+impl std::fmt::Debug for WalletPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WalletPassword(***)")
+    }
+}
 
 impl WalletState {
     /// simple/fast get mel balance only
@@ -586,14 +1112,35 @@ impl WalletState {
         Ok( self.0.summary().await?.detailed_balance.get("6d").copied().unwrap_or(CoinValue(0)) )
     }
 
-    /// unlock mint-wallet, first try plaintext, second try empty password if fails, final return error if still failed.
+    /// unlock mint-wallet by the password if provided; otherwise first try plaintext, second try empty password if fails, final return error if still failed.
     pub async fn unlock(&self) -> surf::Result<()> {
         if self.0.summary().await?.locked {
-            if let Err(_) = self.0.unlock(None).await {
+            if let Some(pw) = &self.1 {
+                self.0.unlock(Some(pw.0.clone())).await?;
+            } else if let Err(_) = self.0.unlock(None).await {
                 self.0.unlock(Some("".to_string())).await?;
             }
         }
         Ok(())
     }
+
+    /// lock mint-wallet again after signing (only if it has a password), to limit the exposure of an unlocked wallet in the daemon.
+    pub async fn relock(&self) -> surf::Result<()> {
+        if self.1.is_some() {
+            self.0.lock().await?;
+        }
+        Ok(())
+    }
+
+    /// Unlocks mint-wallet for `sign` (preparing a tx, and sending it), and locks it again after. only one signing at a time:
+    /// the seeds, the proofs and the settlement are sent concurrently, a relock of one must never lock the wallet while another is signing,
+    /// and two txs must never be prepared from the same unspent coins.
+    pub async fn signing<T>(&self, sign: impl Future<Output = T>) -> surf::Result<T> {
+        let _signing = self.2.lock().await;
+        self.unlock().await?;
+        let out = sign.await;
+        self.relock().await?;
+        Ok(out)
+    }
 }
 
@@ -1,6 +1,15 @@
 use crate::*;
 use crate::db::*;
 
+// all tests use a temporary database (never the real one of user), must be called before any access.
+fn test_db() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("melminter-mod-test-{}", std::process::id()));
+        set_db_path(dir.join(DB_FILENAME));
+    });
+}
+
 #[test]
 fn nva_test() {
     for i in 0..10000 {
@@ -10,6 +19,7 @@ fn nva_test() {
 
 #[test]
 fn db_test() {
+    test_db();
     for _ in 0..2 {
         println!("boringdb path {:?}", db_path());
         let tab = dict_open("testable").unwrap();
@@ -28,6 +38,7 @@ fn db_test() {
 
 #[test]
 fn db_without_flush() {
+    test_db();
     let mut n=fastrand::u32(..);
     let mut ks = vec![];
     let na = 20349;
@@ -73,3 +84,738 @@ fn nnd_test() {
         println!("null[{}] {}", i+1, new_null_dst());
     }
 }
+
+#[test]
+fn proof_migrate_test() {
+    use std::time::SystemTime;
+    use themelio_structs::{BlockHeight, CoinData, CoinDataHeight, CoinID, CoinValue, Denom, TxHash};
+    test_db();
+
+    let tn = format!("proofmigrate_{}", fastrand::u64(..));
+    let mut map = Map::new();
+    map.dict(&tn).unwrap();
+
+    let old = TrySendProofV0 {
+        coin: CoinID { txhash: TxHash(tmelcrypt::HashVal([1u8; 32])), index: 0 },
+        data: CoinDataHeight {
+            coin_data: CoinData {
+                covhash: new_void_address(),
+                value: CoinValue(1),
+                denom: Denom::NewCoin,
+                additional_data: vec![],
+            },
+            height: BlockHeight(100),
+        },
+        proof: vec![1, 2, 3],
+    };
+    let old_state = TrySendProofStateV0 {
+        fails: 1,
+        created: SystemTime::now(),
+        sent: false,
+        failed: false,
+        errors: vec!["test".to_string()],
+    };
+    // write as a version 0 database (without envelope)
+    map.cur().set(&bincode::serialize(&old).unwrap(), &bincode::serialize(&old_state).unwrap()).unwrap();
+
+    assert_eq!(migrate_proofs(&mut map.cur()).unwrap(), 1);
+    // nothing to do in second time
+    assert_eq!(migrate_proofs(&mut map.cur()).unwrap(), 0);
+
+    let keys = map.cur().keys().unwrap();
+    assert_eq!(keys.len(), 1);
+    let new: TrySendProof = bincode::deserialize(keys.iter().next().unwrap()).unwrap();
+    assert_eq!(new.coin, old.coin);
+    assert_eq!(new.proof, old.proof);
+    assert_eq!(new.difficulty, 0);
+
+    // the migration v0 -> v1 writes the records of version 1
+    let raw = map.cur().get(&bincode::serialize(&new).unwrap()).unwrap().unwrap();
+    let env: EnvelopeV1 = bincode::deserialize(&raw).unwrap();
+    assert_eq!(env.version, 1);
+    let state: TrySendProofState = bincode::deserialize(&env.body).unwrap();
+    assert_eq!(state.status, ProofStatus::Pending);
+    assert_eq!(state.errors, old_state.errors);
+}
+
+#[test]
+fn binary_key_test() {
+    // the keys are bincode encoded, any first byte is allowed
+    assert!(DictMap::to_key(b"_proof").is_ok());
+    assert!(DictMap::to_key(b".proof").is_ok());
+    assert!(DictMap::to_key(b"").is_err());
+    assert!(DictMap::to_key(b"_metadata.keys").is_err());
+}
+
+#[test]
+fn schema_version_test() {
+    test_db();
+    db_init().unwrap();
+    assert_eq!(schema_version().unwrap(), SCHEMA_VERSION);
+    // second time is no-op
+    db_init().unwrap();
+}
+
+#[test]
+fn cipher_test() {
+    use crate::dbcrypt::Cipher;
+
+    let (cipher, info) = Cipher::create("test passphrase");
+    for len in [0usize, 1, 31, 32, 33, 1000] {
+        let plain: Vec<u8> = (0..len).map(|_| fastrand::u8(..)).collect();
+        let sealed = cipher.encrypt(&plain);
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), plain);
+
+        // any modification should be detected
+        let mut bad = sealed.clone();
+        let i = fastrand::usize(..bad.len());
+        bad[i] ^= 1;
+        assert!(cipher.decrypt(&bad).is_none());
+    }
+
+    assert!(Cipher::open("test passphrase", &info).is_some());
+    assert!(Cipher::open("wrong passphrase", &info).is_none());
+}
+
+#[test]
+fn expired_seeds_opt_test() {
+    use crate::state::ExpiredSeeds;
+    assert_eq!("keep".parse::<ExpiredSeeds>().unwrap(), ExpiredSeeds::Keep);
+    assert_eq!("Consolidate".parse::<ExpiredSeeds>().unwrap(), ExpiredSeeds::Consolidate);
+    assert_eq!("burn".parse::<ExpiredSeeds>().unwrap(), ExpiredSeeds::Burn);
+    assert!("t1m9v0fhkbr7q1sfg59prke1sbpt0gm2qgrb166mp8n8m59962gdm0".parse::<ExpiredSeeds>().is_err());
+}
+
+// a fake chain of the mint wallet, for test the real seed generation and minting without daemon
+mod fake_chain {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    use async_trait::async_trait;
+    use themelio_nodeprot::ValClient;
+    use themelio_structs::{Address, BlockHeight, CoinData, CoinDataHeight, CoinID, CoinValue, Denom, NetID, TxHash, TxKind};
+    use tmelcrypt::HashVal;
+    use crate::backend::Chain;
+    use crate::db::{write_seed, SeedRecord, SeedState};
+    use crate::state::{FeeSchedule, MintState};
+
+    // the difficulty of test proofs (fast enough for hundreds of threads)
+    pub const DIFFICULTY: usize = 8;
+    const HEIGHT: u64 = 100;
+    const FEE: CoinValue = CoinValue(1);
+
+    #[derive(Debug, Default)]
+    struct Ledger {
+        coins: HashMap<CoinID, CoinData>,
+        txs: usize,
+    }
+
+    #[derive(Debug)]
+    pub struct FakeChain {
+        address: Address,
+        ledger: Mutex<Ledger>,
+    }
+
+    impl FakeChain {
+        pub fn new() -> Arc<Self> {
+            Arc::new(Self {
+                address: crate::new_void_address(),
+                ledger: Mutex::default(),
+            })
+        }
+
+        /// a mint state that uses this chain
+        pub fn mint_state(self: &Arc<Self>, bulk: bool) -> MintState {
+            let client = ValClient::new(NetID::Testnet, "127.0.0.1:11814".parse().unwrap());
+            let mut state = MintState::with_chain(self.clone(), client, FeeSchedule {
+                history: vec![],
+                allow_any_tx: true,
+                no_failsafe: true,
+                max_lost: CoinValue(0),
+                quit: false,
+            });
+            if bulk {
+                state.seed_handler.bulk();
+            }
+            state
+        }
+
+        /// how many txs sent
+        pub fn txs(&self) -> usize {
+            self.ledger.lock().unwrap().txs
+        }
+
+        /// adds a coin to the wallet, without tx
+        pub fn insert(&self, id: CoinID, data: CoinData) {
+            self.ledger.lock().unwrap().coins.insert(id, data);
+        }
+
+        /// spends a coin (as the DoscMint tx of its proof)
+        pub fn spend(&self, id: CoinID) {
+            assert!(self.ledger.lock().unwrap().coins.remove(&id).is_some(), "spent a missing coin {:?}", id);
+        }
+
+        /// the seeds of an earlier run: a seed tx of `count` unit seeds, or a bulk seed of `count`
+        pub fn new_seeds(&self, bulk: bool, count: usize) -> TxHash {
+            let values = if bulk { vec![count as u128] } else { vec![1; count] };
+            let outputs = values.iter().map(|v| CoinData {
+                covhash: self.address,
+                denom: Denom::NewCoin,
+                value: CoinValue(*v),
+                additional_data: vec![],
+            }).collect();
+            let (txhash, _) = smol::block_on(self.send(TxKind::Normal, vec![], outputs, vec![], vec![], None)).unwrap();
+            let now = SystemTime::now();
+            write_seed(&SeedRecord {
+                txhash,
+                height: HEIGHT,
+                threads: count,
+                bulk,
+                ttl: None,
+                state: SeedState::Fresh,
+                created: now,
+                updated: now,
+            }).unwrap();
+            txhash
+        }
+    }
+
+    #[async_trait]
+    impl Chain for FakeChain {
+        async fn address(&self) -> surf::Result<Address> {
+            Ok(self.address)
+        }
+
+        async fn is_testnet(&self) -> surf::Result<bool> {
+            Ok(true)
+        }
+
+        async fn balance(&self) -> surf::Result<CoinValue> {
+            Ok(CoinValue(1_000_000))
+        }
+
+        async fn coins(&self) -> surf::Result<HashMap<CoinID, CoinData>> {
+            Ok(self.ledger.lock().unwrap().coins.clone())
+        }
+
+        async fn send(
+            &self,
+            _kind: TxKind,
+            inputs: Vec<CoinID>,
+            outputs: Vec<CoinData>,
+            _data: Vec<u8>,
+            _nobalance: Vec<Denom>,
+            max_fee: Option<CoinValue>,
+        ) -> surf::Result<(TxHash, CoinValue)> {
+            if max_fee.map(|max| FEE > max).unwrap_or(false) {
+                return Err(surf::Error::new(403, anyhow::Error::msg("refused to send any high-fee tx.")));
+            }
+            // CoinID.index just unsigned 8-bit integer
+            assert!(outputs.len() <= 0x100, "too many outputs of a tx: {}", outputs.len());
+
+            let mut ledger = self.ledger.lock().unwrap();
+            for id in &inputs {
+                if ledger.coins.remove(id).is_none() {
+                    return Err(surf::Error::new(400, anyhow::Error::msg(format!("spent a missing coin {:?}", id))));
+                }
+            }
+            let txhash = TxHash(HashVal::random());
+            for (index, mut data) in outputs.into_iter().enumerate() {
+                // the new-coin token is named by its tx
+                if data.denom == Denom::NewCoin {
+                    data.denom = Denom::Custom(txhash);
+                }
+                ledger.coins.insert(CoinID { txhash, index: index as u8 }, data);
+            }
+            ledger.txs += 1;
+            Ok((txhash, FEE))
+        }
+
+        async fn prepare_fee(
+            &self,
+            _kind: TxKind,
+            _inputs: Vec<CoinID>,
+            _outputs: Vec<CoinData>,
+            _data: Vec<u8>,
+            _nobalance: Vec<Denom>,
+        ) -> surf::Result<CoinValue> {
+            Ok(FEE)
+        }
+
+        async fn wait(&self, _txhash: TxHash) -> surf::Result<u64> {
+            Ok(HEIGHT)
+        }
+
+        async fn height(&self) -> surf::Result<u64> {
+            Ok(HEIGHT)
+        }
+
+        async fn get_coin(&self, id: CoinID) -> surf::Result<Option<CoinDataHeight>> {
+            Ok(self.ledger.lock().unwrap().coins.get(&id).map(|data| CoinDataHeight {
+                coin_data: data.clone(),
+                height: BlockHeight(HEIGHT),
+            }))
+        }
+
+        async fn header_hash(&self, _height: BlockHeight) -> surf::Result<HashVal> {
+            Ok(HashVal([7u8; 32]))
+        }
+    }
+
+    /// the seeds in wallet, by the real seed selection
+    pub fn seeds(state: &MintState) -> HashMap<CoinID, CoinData> {
+        smol::block_on(state.seed_handler.clone().raw(HEIGHT)).unwrap()
+    }
+
+    /// generates the seeds for `threads` by the real SeedSchedule
+    pub fn generate(state: &mut MintState, threads: usize) {
+        smol::block_on(state.seed_handler.generate(threads, &mut state.fee_handler)).unwrap();
+    }
+
+    /// mints a round by the real mint threads, and spends the used seeds (as the DoscMint txs)
+    pub fn mint(state: &MintState, chain: &FakeChain, threads: usize) -> Vec<CoinID> {
+        let proofs = smol::block_on(state.mint_batch(&vec![DIFFICULTY; threads], || {}, |_, _| {}, |_| {}, threads)).unwrap();
+        assert_eq!(proofs.len(), threads);
+        for trys in &proofs {
+            assert_eq!(trys.data.coin_data.value, CoinValue(1));
+            chain.spend(trys.coin);
+        }
+        proofs.iter().map(|trys| trys.coin).collect()
+    }
+}
+
+#[test]
+fn bulk_seeds_test() {
+    use fake_chain::{FakeChain, generate, mint, seeds};
+    test_db();
+
+    let chain = FakeChain::new();
+    let mut state = chain.mint_state(true);
+    generate(&mut state, 8);
+    // a bulk seed tx, and its split tx
+    assert_eq!(chain.txs(), 2);
+    assert_eq!(seeds(&state).len(), 8);
+    mint(&state, &chain, 8);
+    assert!(seeds(&state).is_empty());
+
+    // thread count decreased: the leftover seeds are used by later rounds without any new tx
+    generate(&mut state, 8);
+    let txs = chain.txs();
+    generate(&mut state, 3);
+    let used = mint(&state, &chain, 3);
+    // the seed tx is partially used: still fresh (not proved), its leftover seeds are usable
+    assert_eq!(load_seeds().unwrap()[&used[0].txhash].state, SeedState::Fresh);
+    generate(&mut state, 3);
+    mint(&state, &chain, 3);
+    assert_eq!(chain.txs(), txs);
+
+    // thread count increased: only the missing seeds are generated
+    assert_eq!(seeds(&state).len(), 2);
+    generate(&mut state, 6);
+    assert_eq!(seeds(&state).len(), 6);
+    let used = mint(&state, &chain, 6);
+    // all seeds of these txs are used
+    for id in used {
+        assert_eq!(load_seeds().unwrap()[&id.txhash].state, SeedState::Proved);
+    }
+}
+
+#[test]
+fn mixed_seeds_test() {
+    use fake_chain::{FakeChain, generate, mint, seeds};
+    use themelio_structs::{CoinData, CoinID, CoinValue, Denom, TxHash};
+    use crate::state::select_seeds;
+    test_db();
+
+    let chain = FakeChain::new();
+    let mut state = chain.mint_state(false);
+    chain.new_seeds(false, 2);
+    chain.new_seeds(true, 3);
+    chain.new_seeds(true, 10);
+
+    // the unit seeds first, then the smallest bulk seed that covers the rest
+    let selected = select_seeds(&seeds(&state), 4);
+    assert_eq!(selected.units.len(), 2);
+    assert_eq!(selected.split.len(), 1);
+    assert_eq!(selected.split[0].1.value, CoinValue(3));
+    assert_eq!(selected.missing, 0);
+
+    // not enough seeds at all
+    let selected = select_seeds(&seeds(&state), 20);
+    assert_eq!(selected.split.len(), 2);
+    assert_eq!(selected.missing, 5);
+
+    // only the bulk seed of 3 is split
+    let txs = chain.txs();
+    generate(&mut state, 4);
+    assert_eq!(chain.txs(), txs + 1);
+    mint(&state, &chain, 4);
+    // one unit seed left, and the bulk seed of 10 is still there
+    assert_eq!(seeds(&state).len(), 2);
+
+    // the consolidated expired seeds are not seeds
+    let other = TxHash(tmelcrypt::HashVal::random());
+    let consolidated = CoinID { txhash: TxHash(tmelcrypt::HashVal::random()), index: 0 };
+    chain.insert(consolidated, CoinData {
+        covhash: crate::new_void_address(),
+        denom: Denom::Custom(other),
+        value: CoinValue(5),
+        additional_data: vec![],
+    });
+    assert!(!seeds(&state).contains_key(&consolidated));
+}
+
+#[test]
+fn many_threads_seeds_test() {
+    use std::collections::HashSet;
+    use fake_chain::{FakeChain, generate, mint, seeds};
+    use crate::state::MAX_TX_OUTPUTS;
+    test_db();
+
+    // more threads than the outputs of a tx
+    let threads = MAX_TX_OUTPUTS + 129;
+    for bulk in [false, true] {
+        let chain = FakeChain::new();
+        let mut state = chain.mint_state(bulk);
+        generate(&mut state, threads);
+        // needs two seed txs (and the split txs if bulk)
+        assert_eq!(chain.txs(), if bulk { 4 } else { 2 });
+
+        let used = mint(&state, &chain, threads);
+        let txs: HashSet<_> = used.iter().map(|id| id.txhash).collect();
+        assert_eq!(txs.len(), 2);
+        assert!(seeds(&state).is_empty());
+    }
+}
+
+#[test]
+fn next_seeds_test() {
+    use fake_chain::{FakeChain, DIFFICULTY, generate, seeds};
+    test_db();
+
+    let chain = FakeChain::new();
+    let mut state = chain.mint_state(false);
+    generate(&mut state, 4);
+    assert_eq!(chain.txs(), 1);
+
+    // the seeds of next round are generated while minting (after the seeds of this round selected), never counts the seeds in use
+    let (send_started, recv_started) = smol::channel::bounded(1);
+    let mut next_seeds = state.seed_handler.clone();
+    let mut next_fees = state.fee_handler.clone();
+    let (proofs, ()) = smol::block_on(smol::future::zip(
+        state.mint_batch(&[DIFFICULTY; 4], move || { let _ = send_started.try_send(()); }, |_, _| {}, |_| {}, 4),
+        async {
+            recv_started.recv().await.unwrap();
+            next_seeds.generate(4, &mut next_fees).await.unwrap();
+        },
+    ));
+    assert_eq!(chain.txs(), 2);
+
+    // the seeds of finished proofs are still in use until spent
+    let proofs = proofs.unwrap();
+    assert_eq!(proofs.len(), 4);
+    assert_eq!(seeds(&state).len(), 4);
+    for trys in &proofs {
+        assert!(!seeds(&state).contains_key(&trys.coin));
+        chain.spend(trys.coin);
+    }
+    assert_eq!(seeds(&state).len(), 4);
+}
+
+#[test]
+fn fee_order_test() {
+    use std::time::{Duration, SystemTime};
+    use themelio_structs::{CoinValue, TxKind};
+    use crate::state::{FeeRecord, FeeSchedule};
+
+    let t0 = SystemTime::now() - Duration::from_secs(100);
+    let rec = |kind, secs, fee, income| FeeRecord {
+        kind,
+        time: t0 + Duration::from_secs(secs),
+        balance: CoinValue(1000),
+        fee: CoinValue(fee),
+        income: CoinValue(income),
+    };
+    let mut fees = FeeSchedule {
+        history: vec![],
+        allow_any_tx: true,
+        no_failsafe: false,
+        max_lost: CoinValue(1000),
+        quit: false,
+    };
+
+    // the streamed proofs of round 2 are recorded before the settlement of round 1 (in background) finished
+    fees.record(vec![rec(TxKind::Normal, 1, 5, 0), rec(TxKind::DoscMint, 10, 3, 0)]);
+    fees.record(vec![rec(TxKind::DoscMint, 30, 4, 0)]);
+    fees.record(vec![rec(TxKind::Swap, 20, 2, 50), rec(TxKind::DoscMint, 25, 3, 0)]);
+
+    let times: Vec<_> = fees.history.iter().map(|it| it.time.duration_since(t0).unwrap().as_secs()).collect();
+    assert_eq!(times, vec![1, 10, 20, 25, 30]);
+    assert_eq!(fees.last_fee(TxKind::DoscMint), Some(CoinValue(4)));
+    // never panics
+    fees.failsafe();
+}
+
+#[test]
+fn cpu_plan_test() {
+    use crate::affinity::{plan, CpuInfo, CpuList};
+
+    assert_eq!("0-3,8,10-11".parse::<CpuList>().unwrap(), CpuList(vec![0, 1, 2, 3, 8, 10, 11]));
+    assert!("3-1".parse::<CpuList>().is_err());
+    assert!("".parse::<CpuList>().is_err());
+
+    // 2 nodes, 2 cores per node, 2 hyper-threads per core (cpu N and N+4 are siblings)
+    let topo: Vec<CpuInfo> = (0..8).map(|id| CpuInfo {
+        id,
+        core: (id % 4 / 2, id % 4),
+        node: id % 4 / 2,
+    }).collect();
+
+    let (cpus, cores) = plan(&topo, None).unwrap();
+    assert_eq!(cores, 4);
+    // one per physical core first (node by node), then the hyper-threads
+    assert_eq!(cpus, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+
+    let (cpus, cores) = plan(&topo, Some(&"2-3,6-7".parse().unwrap())).unwrap();
+    assert_eq!(cores, 2);
+    assert_eq!(cpus, vec![2, 3, 6, 7]);
+
+    assert!(plan(&topo, Some(&"8".parse().unwrap())).is_err());
+}
+
+#[test]
+fn schedule_test() {
+    use std::time::Duration;
+    use crate::schedule::Schedule;
+
+    const DAY: u32 = 24 * 60;
+    let sched: Schedule = "Mon-Fri 18:00-08:00; Sat,Sun 00:00-24:00".parse().unwrap();
+    // Monday 12:00 / 18:00 / 23:59
+    assert!(!sched.contains(12 * 60));
+    assert!(sched.contains(18 * 60));
+    assert!(sched.contains(DAY - 1));
+    // Tuesday 07:59 (crossing midnight) and 08:00
+    assert!(sched.contains(DAY + 8 * 60 - 1));
+    assert!(!sched.contains(DAY + 8 * 60));
+    // Sunday 23:00, and Monday 07:00 (the weekend windows end at midnight)
+    assert!(sched.contains(6 * DAY + 23 * 60));
+    assert!(!sched.contains(7 * 60));
+
+    // Monday 17:00:30 waits 59.5 minutes, then 14 hours lefts
+    assert_eq!(sched.wait((17 * 60, 30)), Some(Duration::from_secs(59 * 60 + 30)));
+    assert_eq!(sched.remaining((17 * 60, 30)), None);
+    assert_eq!(sched.remaining((18 * 60, 0)), Some(Duration::from_secs(14 * 3600)));
+    // Friday 18:00 lefts the whole weekend (until Monday 00:00)
+    assert_eq!(sched.remaining((4 * DAY + 18 * 60, 0)), Some(Duration::from_secs((6 + 48) * 3600)));
+
+    let always: Schedule = "00:00-24:00".parse().unwrap();
+    assert_eq!(always.remaining((0, 0)), Some(Duration::MAX));
+
+    assert!("Mon 08:00".parse::<Schedule>().is_err());
+    assert!("Xyz 08:00-09:00".parse::<Schedule>().is_err());
+    assert!("08:00-08:00".parse::<Schedule>().is_err());
+    assert!("25:00-26:00".parse::<Schedule>().is_err());
+}
+
+#[test]
+fn governor_test() {
+    use crate::governor::Governor;
+
+    let g = Governor { max_load: Some(2.0), max_temp: Some(80.0) };
+    // 8 threads active, load 12 = 4 of others
+    assert_eq!(g.decide(Some(12.0), Some(60.0), 8, 8), 7);
+    // too hot: reduce a quarter
+    assert_eq!(g.decide(Some(8.0), Some(85.0), 8, 8), 6);
+    assert_eq!(g.decide(Some(1.0), Some(85.0), 1, 8), 0);
+    // in the hysteresis: keep
+    assert_eq!(g.decide(Some(6.0), Some(78.0), 6, 8), 6);
+    // cool and idle: resume one by one
+    assert_eq!(g.decide(Some(6.0), Some(60.0), 6, 8), 7);
+    assert_eq!(g.decide(Some(8.0), Some(60.0), 8, 8), 8);
+
+    // unreadable: never throttle
+    assert_eq!(g.decide(None, None, 3, 8), 4);
+}
+
+#[test]
+fn cancel_test() {
+    use crate::control::MintControl;
+
+    let control = MintControl::default();
+    assert_eq!(control.run(|| { control.checkpoint(0); 1 }), Some(1));
+
+    // only the cancelled thread stops
+    control.cancel_thread(1);
+    assert_eq!(control.run(|| { control.checkpoint(0); 1 }), Some(1));
+    assert_eq!(control.run(|| { control.checkpoint(1); 1 }), None);
+
+    // a paused thread also stops by cancel
+    control.pause();
+    control.cancel();
+    assert_eq!(control.run(|| { control.checkpoint(0); 1 }), None);
+
+    // other panics are not caught
+    assert!(std::panic::catch_unwind(|| control.run(|| panic!("boom"))).is_err());
+
+    control.reset();
+    assert_eq!(control.run(|| { control.checkpoint(1); 1 }), Some(1));
+
+    // a stop request is kept by the reset of next round
+    control.stop();
+    control.reset();
+    assert!(control.is_cancelled());
+    assert_eq!(control.run(|| { control.checkpoint(0); 1 }), None);
+}
+
+#[test]
+fn calibration_test() {
+    use crate::db::{BenchPoint, Calibration};
+
+    let c = Calibration {
+        time: std::time::SystemTime::now(),
+        cpu_model: "test".to_string(),
+        threads: 5,
+        points: vec![
+            BenchPoint { difficulty: 10, single: 100.0, multi: 60.0 },
+            BenchPoint { difficulty: 20, single: 80.0, multi: 40.0 },
+        ],
+    };
+    assert_eq!(c.speed(10, 1), Some(100.0));
+    assert_eq!(c.speed(10, 5), Some(60.0));
+    // between single and all threads
+    assert_eq!(c.speed(10, 3), Some(80.0));
+    // more threads than measured
+    assert_eq!(c.speed(10, 10), Some(30.0));
+    // between difficulties, and outside of them
+    assert_eq!(c.speed(15, 1), Some(90.0));
+    assert_eq!(c.speed(5, 1), Some(100.0));
+    assert_eq!(c.speed(30, 5), Some(40.0));
+
+    let empty = Calibration { points: vec![], ..c };
+    assert_eq!(empty.speed(10, 1), None);
+    assert!(! empty.matches());
+}
+
+#[test]
+fn round_correction_test() {
+    use crate::bench::correction;
+    use crate::db::{RoundTiming, SpeedSource};
+
+    let round = |difficulty, threads, predicted, actual| RoundTiming {
+        time: std::time::SystemTime::now(),
+        difficulty, threads, predicted, actual,
+        source: SpeedSource::Live,
+    };
+
+    assert_eq!(correction(&[], Some(20), 4, SpeedSource::Live), (1.0, 0));
+
+    // 2x slower and 2x faster are even
+    let rounds = vec![round(20, 4, 100.0, 200.0), round(20, 4, 100.0, 50.0)];
+    let (f, n) = correction(&rounds, None, 4, SpeedSource::Live);
+    assert!((f - 1.0).abs() < 1e-9);
+    assert_eq!(n, 2);
+    // other thread count is not used
+    assert_eq!(correction(&rounds, None, 8, SpeedSource::Live), (1.0, 0));
+    // the rounds predicted by the live speed never correct the calibration (it would be corrected twice)
+    assert_eq!(correction(&rounds, None, 4, SpeedSource::Calibration), (1.0, 0));
+
+    // prefers the same difficulty if enough
+    let mut rounds = vec![round(18, 4, 100.0, 100.0); 5];
+    assert_eq!(correction(&rounds, Some(20), 4, SpeedSource::Live).1, 5);
+    rounds.extend(vec![round(20, 4, 100.0, 150.0); 3]);
+    let (f, n) = correction(&rounds, Some(20), 4, SpeedSource::Live);
+    assert!((f - 1.5).abs() < 1e-9);
+    assert_eq!(n, 3);
+
+    // limited
+    let rounds = vec![round(20, 4, 1.0, 1000.0)];
+    assert_eq!(correction(&rounds, None, 4, SpeedSource::Live).0, 4.0);
+}
+
+#[test]
+fn solve_difficulty_test() {
+    use crate::bench::solve_difficulty;
+
+    // 1024 H/s: difficulty 20 is 1024s
+    assert_eq!(solve_difficulty(1024.0, |_| 1024.0), 20);
+    // nearest in log scale: 1400s is nearer to 1024s than 2048s
+    assert_eq!(solve_difficulty(1400.0, |_| 1024.0), 20);
+    assert_eq!(solve_difficulty(1500.0, |_| 1024.0), 21);
+    // slower at higher difficulty
+    assert_eq!(solve_difficulty(1800.0, |_| 1024.0), 21);
+    assert_eq!(solve_difficulty(1800.0, |d| if d > 20 { 512.0 } else { 1024.0 }), 20);
+    // limited range
+    assert_eq!(solve_difficulty(0.001, |_| 1024.0), 10);
+}
+
+#[test]
+fn thread_difficulties_test() {
+    use crate::bench::thread_difficulties;
+
+    // unknown speeds: all the same
+    assert_eq!(thread_difficulties(20, &[None, None]), vec![20, 20]);
+    // a 2x slower core gets one less, a 1.2x slower one keeps
+    assert_eq!(thread_difficulties(20, &[Some(100.0), Some(100.0), Some(50.0), Some(80.0), None]), vec![20, 20, 19, 20, 20]);
+    // never higher, and at most 3 less
+    assert_eq!(thread_difficulties(20, &[Some(400.0), Some(100.0), Some(100.0), Some(1.0)]), vec![20, 20, 20, 17]);
+}
+
+#[test]
+fn stream_proofs_test() {
+    use std::time::Duration;
+    use themelio_structs::{BlockHeight, CoinData, CoinDataHeight, CoinID, CoinValue, Denom, TxHash};
+    use crate::worker::stream_proofs;
+    test_db();
+
+    let proofs: Vec<TrySendProof> = (0..3).map(|thread| TrySendProof {
+        coin: CoinID { txhash: TxHash(tmelcrypt::HashVal::random()), index: 0 },
+        data: CoinDataHeight {
+            coin_data: CoinData {
+                covhash: new_void_address(),
+                value: CoinValue(1),
+                denom: Denom::NewCoin,
+                additional_data: vec![],
+            },
+            height: BlockHeight(100),
+        },
+        proof: vec![1, 2, 3],
+        difficulty: 8,
+        chi: tmelcrypt::HashVal::random(),
+        thread,
+        elapsed: Duration::from_secs(1),
+    }).collect();
+    let stored = |trys: &TrySendProof| -> Option<TrySendProofState> {
+        let mut map = Map::new();
+        map.dict(TABLE_PROOF_LIST).unwrap();
+        map.get(trys).unwrap().map(|v| *v)
+    };
+
+    let (send_proof, recv_proof) = smol::channel::unbounded();
+    for trys in &proofs {
+        send_proof.try_send(trys.clone()).unwrap();
+    }
+    std::mem::drop(send_proof);
+
+    // a mocked wallet: the proof of thread 1 cannot be sent
+    let (sent, unsent, results) = smol::block_on(stream_proofs(recv_proof, |trys, mut tryst| async move {
+        // stored before submit
+        assert_eq!(stored(&trys).expect("the proof is not stored before submit").status, ProofStatus::Pending);
+        if trys.thread == 1 {
+            tryst.fails += 1;
+            return (tryst, Err(surf::Error::new(500, anyhow::Error::msg("daemon is down"))));
+        }
+        let txhash = TxHash(tmelcrypt::HashVal::random());
+        tryst.status = ProofStatus::Submitted(txhash);
+        (tryst, Ok(Some((txhash, trys.thread))))
+    }));
+    assert_eq!(results, vec![0, 2]);
+    assert_eq!(sent.len(), 2);
+    assert_eq!(unsent.len(), 1);
+    assert_eq!(unsent[0].0.thread, 1);
+
+    // the result of submission is also stored
+    assert!(matches!(stored(&proofs[0]).unwrap().status, ProofStatus::Submitted(_)));
+    let failed = stored(&proofs[1]).unwrap();
+    assert_eq!(failed.status, ProofStatus::Pending);
+    assert_eq!(failed.fails, 1);
+}
@@ -1,5 +1,6 @@
 use std::{
     collections::{HashMap, VecDeque},
+    future::Future,
     net::SocketAddr,
     sync::{Arc, Mutex},
     time::{Duration, Instant, SystemTime},
@@ -7,10 +8,14 @@ use std::{
 
 use crate::{
     repeat_fallible,
-    state::{MintState, FeeSchedule},
-    db::{self, TrySendProof, TrySendProofState, TABLE_PROOF_LIST},
+    state::{MintState, FeeSchedule, FeeRecord, WalletPassword, proof_reward},
+    db::{self, TrySendProof, TrySendProofState, ProofStatus, SeedState, SpeedSource, TABLE_PROOF_LIST},
     CmdOpts,
-    panic_exit
+    panic_exit,
+    schedule,
+    bench,
+    governor::{self, Governor},
+    control::MintControl,
 };
 use bincode;
 
@@ -24,23 +29,24 @@ use smol::{
 use themelio_nodeprot::ValClient;
 use themelio_stf::Tip910MelPowHash;
 use themelio_structs::{
-    Address, CoinData,
-    CoinDataHeight, CoinID,
+    Address,
     CoinValue, Denom,
     NetID,
-    PoolKey, TxKind,
+    PoolKey, TxHash, TxKind,
 };
 
 /// Worker configuration
 #[derive(Clone, Debug)]
 pub struct WorkerConfig {
     pub wallet: WalletClient,
+    pub wallet_password: Option<WalletPassword>,
     pub payout: Option<Address>,
     pub connect: SocketAddr,
     pub netid: NetID,
     //pub name: String,
     pub tree: prodash::Tree,
     pub threads: usize,
+    pub cpus: Option<Vec<usize>>, // pin mint thread N to the CPU cpus[N % len]
 
     pub cli_opts: CmdOpts,
 }
@@ -48,6 +54,7 @@ pub struct WorkerConfig {
 /// Represents a worker.
 pub struct Worker {
     send_stop: Sender<()>,
+    control: Arc<MintControl>,
     _task: smol::Task<surf::Result<()>>,
 }
 
@@ -55,15 +62,18 @@ impl Worker {
     /// Starts a worker with the given WorkerConfig.
     pub fn start(config: WorkerConfig) -> Self {
         let (send_stop, recv_stop) = smol::channel::bounded(1);
+        let control = Arc::new(MintControl::default());
         Self {
             send_stop,
-            _task: smol::spawn(main_async(config, recv_stop)),
+            control: control.clone(),
+            _task: smol::spawn(main_async(config, recv_stop, control)),
         }
     }
 
-    /// Send a stop request to the worker
+    /// Send a stop request to the worker, the running mint threads are cancelled (the finished proofs will be submitted before stop)
     pub async fn stop(&self) -> anyhow::Result<()> {
         self.send_stop.send(()).await?;
+        self.control.stop();
         Ok(())
     }
     /*
@@ -73,7 +83,7 @@ impl Worker {
     }*/
 }
 
-async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result<()> {
+async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>, control: Arc<MintControl>) -> surf::Result<()> {
     let tree = opts.tree.clone();
 
     #[allow(unreachable_code)]
@@ -95,7 +105,7 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
         let allow_any_tx =         if is_testnet { true } else { cli_opts.allow_any_tx };
 
         // initial mint state with fee policy
-        let mut mint_state = MintState::new(opts.wallet.clone(), client.clone(),
+        let mut mint_state = MintState::new(opts.wallet.clone(), opts.wallet_password.clone(), client.clone(),
             FeeSchedule {
                 allow_any_tx,
                 history: vec![],
@@ -106,6 +116,10 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
         if bulk_seeds {
             mint_state.seed_handler.bulk();
         }
+        mint_state.seed_handler.expired_handling(cli_opts.expired_seeds, cli_opts.sweep_dry_run);
+        mint_state.seed_handler.ttl_override(cli_opts.seed_ttl);
+        mint_state.set_affinity(opts.cpus.clone());
+        mint_state.control = control.clone();
 
         // establish a connection to local disk storage for saves un-sent proofs.
 
@@ -118,34 +132,161 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
         map.dict(TABLE_PROOF_LIST)?;
 
         // A queue for any proofs that waiting to submit (global store / also possible from disk...)
-        let mut submit_proofs: HashMap<TrySendProof, TrySendProofState> = HashMap::new();
-        // init load from disk
-        for key in map.cur().keys()? {
-            let key = bincode::deserialize(&key)?;
-            if let Some(val) = map.get(&key)? {
-                submit_proofs.insert(key, *val);
-            } else {
-                log::warn!("missing hit a key {:?} of TABLE_PROOF_LIST: unexpected none value! CHECK METADATA LOGIC", key);
+        // init load from disk: only the pending proofs, any submitted/confirmed/failed proofs should never be re-submit.
+        let mut submit_proofs: VecDeque<(TrySendProof, TrySendProofState)> = VecDeque::new();
+        // how long to keep the records of finished proofs
+        let proof_retention = Duration::from_secs(cli_opts.proof_retention_days * 86400);
+
+        // the proofs submitted before restart: the confirmation was never checked, so check them now (all at once, by a single deadline).
+        let recheck_deadline = Instant::now() + RECHECK_TIMEOUT;
+        let mut rechecks = vec![];
+        let mut loaded = vec![];
+        for (trys, tryst) in db::load_proofs(&map)? {
+            match tryst.status {
+                ProofStatus::Submitted(txhash) => {
+                    let wallet = opts.wallet.clone();
+                    let task = smol::spawn(async move { recheck_submitted(&wallet, txhash, recheck_deadline).await });
+                    rechecks.push((txhash, trys, tryst, task));
+                },
+                ProofStatus::Pending => loaded.push((trys, tryst)),
+                _ => {},
+            }
+        }
+        for (txhash, trys, mut tryst, task) in rechecks {
+            match task.await {
+                Some(status) => {
+                    tryst.status = status;
+                },
+                // still unconfirmed (maybe in mempool): re-checked after next restart, until the retention
+                None if tryst.updated.elapsed().unwrap_or_default() <= proof_retention => {
+                    log::info!("a proof submitted before restart is not confirmed yet (tx hash {}), it will be re-checked later", txhash);
+                    continue;
+                },
+                None => {
+                    tryst.status = ProofStatus::Failed(format!("tx {} not confirmed within the retention ({} days)", txhash, cli_opts.proof_retention_days));
+                },
             }
+            tryst.updated = SystemTime::now();
+            log::info!("re-checked a proof submitted before restart (tx hash {}): {:?}", txhash, tryst.status);
+            map.set(&trys, &tryst)?;
         }
+        for (mut trys, mut tryst) in loaded {
+
+            // the migrated proofs does not have difficulty (zero), try to recover it, otherwise never submit it.
+            if trys.difficulty == 0 {
+                let recovered = mint_state.recover_difficulty(trys.coin, &trys.data, &trys.proof).await?;
+                map.remove(&trys)?;
+                if let Some((difficulty, chi)) = recovered {
+                    log::info!("recovered difficulty {} of a migrated proof {:?}", difficulty, trys.coin);
+                    trys.difficulty = difficulty;
+                    trys.chi = chi;
+                } else {
+                    tryst.status = ProofStatus::Quarantined("cannot recover the difficulty of a migrated proof".to_string());
+                    tryst.updated = SystemTime::now();
+                }
+                map.set(&trys, &tryst)?;
 
-        // convert to Vec Deque
-        let mut submit_proofs: VecDeque<(TrySendProof, TrySendProofState)> = {
-            let mut o = VecDeque::new();
-            for (k, v) in submit_proofs {
-                o.push_back((k, v));
+                if tryst.status != ProofStatus::Pending {
+                    continue;
+                }
             }
-            o
-        };
+            // its seed is not spent yet, never mint it again
+            mint_state.seed_handler.in_use.lock().unwrap().insert(trys.coin);
+            submit_proofs.push_back((trys, tryst));
+        }
+        if ! submit_proofs.is_empty() {
+            log::info!("loaded {} pending proofs from local database", submit_proofs.len());
+        }
         //map.flush()?;
 
         if cli_opts.fixed_diff.is_some() && cli_opts.fixed_secs.is_some() {
             panic_exit!(10, "Note: --fixed-diff and --fixed-secs are exclusive and may not co-exist to avoid confusion.");
         }
+
+        // the speed of each mint thread: by the stored calibration (see "bench" subcommand), then by the real speed of finished rounds.
+        let calibration = match db::load_calibration() {
+            Ok(Some(c)) if c.matches() => {
+                log::info!("using the calibration of {:?} ({} threads)", c.cpu_model, c.threads);
+                Some(c)
+            },
+            Ok(Some(c)) => {
+                log::warn!("the stored calibration was measured on another CPU ({:?}), ignored. please run the \"bench\" subcommand again.", c.cpu_model);
+                None
+            },
+            Ok(None) => {
+                log::info!("no calibration stored, the round time is predicted by a short benchmark (run the \"bench\" subcommand for a better one)");
+                None
+            },
+            Err(e) => {
+                log::warn!("cannot read the stored calibration: {:?}", e);
+                None
+            },
+        };
+        let mut live_speed: Option<f64> = None;
+        // the real speed of each CPU (only if the threads are pinned)
+        let mut cpu_speeds: HashMap<usize, f64> = HashMap::new();
+        // the proofs submitted while minting, waiting for confirmation
+        let mut streamed: Vec<(TxHash, TrySendProof, TrySendProofState)> = vec![];
+        // the settlement of previous rounds, running in background
+        let mut settling: Option<Task<surf::Result<Vec<FeeRecord>>>> = None;
         loop {
+            let pruned = prune_proofs(&mut map, proof_retention)?;
+            if pruned > 0 {
+                log::info!("pruned {} old proof records (retention {} days)", pruned, cli_opts.proof_retention_days);
+            }
+            let pruned = db::prune_seeds(proof_retention)?;
+            if pruned > 0 {
+                log::info!("pruned {} old seed records (retention {} days)", pruned, cli_opts.proof_retention_days);
+            }
             map.flush()?;
 
-            let my_speed = compute_speed().await;
+            // the settlement of previous rounds finished in background
+            if let Some(task) = settling.as_mut() {
+                if let Some(fee_recs) = smol::future::poll_once(task).await {
+                    settling = None;
+                    mint_state.fee_handler.record(fee_recs?);
+                    mint_state.fee_handler.failsafe();
+                }
+            }
+
+            // only minting inside the time windows of schedule (checked before any speed measurement)
+            let window_left = match &cli_opts.schedule {
+                None => None,
+                Some(sched) => match sched.remaining(schedule::now()) {
+                    None => {
+                        // not minting, just settle (the previous rounds, also the pending proofs loaded from disk)
+                        let queued = std::mem::take(&mut submit_proofs);
+                        let waits = std::mem::take(&mut streamed);
+                        let fee_recs = settle_after(settling.take(), &opts, &mint_state, &client, &worker, queued, waits).await?;
+                        mint_state.fee_handler.record(fee_recs);
+                        mint_state.fee_handler.failsafe();
+
+                        let wait = sched.wait(schedule::now()).unwrap_or(Duration::from_secs(60));
+                        worker.lock().unwrap().info(format!("outside of the mint schedule, waiting {:?} for next window...", wait));
+                        // wake up at least every 5 minutes (the stop request and local time changes)
+                        smol::Timer::after(wait.min(Duration::from_secs(300))).await;
+                        if recv_stop.try_recv().is_ok() {
+                            log::warn!("melminter process terminating");
+                            std::process::exit(0);
+                        }
+                        continue;
+                    },
+                    left => left,
+                },
+            };
+
+            let (raw_speed, speed_source) = match (live_speed, &calibration) {
+                (Some(speed), _) => (speed, SpeedSource::Live),
+                (None, Some(c)) => (c.speed(c.points.last().unwrap().difficulty, opts.threads).unwrap(), SpeedSource::Calibration),
+                (None, None) => (compute_speed().await, SpeedSource::Benchmark),
+            };
+            // the prediction is corrected by the actual time of recent rounds
+            let rounds = db::load_rounds().unwrap_or_else(|e| {
+                log::warn!("cannot read the recent round timings: {:?}", e);
+                vec![]
+            });
+            let (factor, _) = bench::correction(&rounds, None, opts.threads, speed_source);
+            let my_speed = raw_speed / factor;
             let my_diff_auto: usize = (my_speed * if is_testnet { 120.0 } else { 30000.0 }).log2().ceil() as usize;
 
             let my_diff_fixed: usize =
@@ -155,8 +296,22 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                 };
             let my_diff_secs: Option<u32> = cli_opts.fixed_secs;
 
+            // the speed of each thread at a difficulty (corrected)
+            let speed_at = |difficulty: usize| -> f64 {
+                let raw = match (live_speed, &calibration) {
+                    (None, Some(c)) => c.speed(difficulty, opts.threads).unwrap_or(raw_speed),
+                    _ => raw_speed,
+                };
+                raw / bench::correction(&rounds, Some(difficulty), opts.threads, speed_source).0
+            };
+
             let my_difficulty =
-                if my_diff_fixed <= 0 {
+                if let Some(secs) = my_diff_secs {
+                    // melpow only allows a power of two hashes, so the nearest difficulty
+                    let difficulty = bench::solve_difficulty(secs as f64, speed_at);
+                    log::info!("--fixed-secs {}: difficulty {} (approx. {:.0}s)", secs, difficulty, 2.0f64.powi(difficulty as _) / speed_at(difficulty));
+                    difficulty
+                } else if my_diff_fixed <= 0 {
                     my_diff_auto
                 } else if my_diff_fixed < my_diff_auto {
 //                    let add = (my_diff_auto - my_diff_fixed) / 2;
@@ -170,173 +325,53 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                 } else {
                     my_diff_fixed
                 };
-            let approx_round = {
-                let mut total: f64 = 2.0f64.powi(my_difficulty as _);
-
-                if let Some(approx) = my_diff_secs {
-                    let approx: f64 = approx.into();
-
-                    let mut offset = total / 200.0;
-                    if offset < 4096.0 { offset = 4096.0; } // min 2**12
-
-                    let mut out: f64 = total / my_speed;
-                    let mut count = 0;
-                    while (out - approx).abs() > 180.0 {
-                        // offset change up/down
-                        if out > approx {
-                            total -= offset;
-                        } else {
-                            total += offset;
-                        }
-
-                        out = total / my_speed;
-
-                        count += 1;
-                        if count > 10_000 {
-                            offset -= offset/10.0;
-                            // next 100 times
-                            count -= 100;
-                        }
-                        if count > 20_000 {
-                            // deadline
-                            break;
-                        }
-                    }
-                }
-
-                total / my_speed
+            // the calibration is measured at several difficulties
+            let raw_speed = match (live_speed, &calibration) {
+                (None, Some(c)) => c.speed(my_difficulty, opts.threads).unwrap_or(raw_speed),
+                _ => raw_speed,
             };
-
-            // Time to submit the proofs, first we store all proofs in disk. then for every proof in the batch, we attempt to submit it. If the submission fails, we move on, because there might be some weird race condition with melwalletd going on.
-            // We also attempt to submit transactions in parallel. This is done by retrying always (with max count 3).
-            let mut waits = vec![];
-            if submit_proofs.len() > 0 {
-                let txs = submit_proofs.len();
-
-                let mut sub = worker.lock().unwrap().add_child("submitting proof");
-                sub.init(Some(txs), None);
-
-                //let mut retry_lefts = txs * 10; // retry limit
-                let max_retry: u8 = 3;
-                while submit_proofs.len() > 0 {
-                    let (trys, mut tryst) = submit_proofs.pop_front().unwrap();
-                    let (coin, data, proof) = (trys.coin, &trys.data, &trys.proof);
-
-                    let snap = client.snapshot().await?;
-                    let reward_speed = 2u128.pow(my_difficulty as u32) / (snap.current_header().height.0 + 40 - data.height.0) as u128;
-                    let reward = themelio_stf::calculate_reward(reward_speed * 100, snap.current_header().dosc_speed, my_difficulty as u32, true);
-                    let reward_ergs = themelio_stf::dosc_to_erg(snap.current_header().height, reward);
-
-                    match mint_state.send_mint_transaction(coin, my_difficulty, proof.clone(), reward_ergs.into()).await {
-                        Err(err) => {
-                            /*
-                            if err.to_string().contains("preparation") || err.to_string().contains("timeout") {
-                                ; // make sure try again
-                                let mut sub = sub.add_child("waiting for available coins");
-                                sub.init(None, None);
-                                smol::Timer::after(Duration::from_secs(10)).await;
-                            }
-                            */
-
-                            tryst.fails += 1;
-                            tryst.errors.push( format!("{:?} | {:?}", SystemTime::now(), err) );
-                            log::warn!("FAILED a proof submission for some reason: {:?}", err);
-
-                            if tryst.fails <= max_retry {
-                                submit_proofs.push_back( ( trys.clone(), tryst.clone() ) );
-                            } else {
-                                log::error!("Dropping proof {:?} from submit queue, because reach max retry limit", (coin, data));
-                            }
-                        },
-                        Ok(res) => {
-                            tryst.sent = true;
-                            waits.push(res);
-
-                            sub.inc();
-                            sub.info(format!("(proof sent) minted {} ERG", CoinValue(reward_ergs)));
-                        }
-                    }
-
-                    /*{
-                        let trys_key = bincode::serialize(&trys)?;
-                        let trys_val = bincode::serialize(&tryst)?;
-                        dict_proofs.insert(trys_key, trys_val)?;
-                        dict_proofs.flush()?;
-                    }*/
-                    map.set(trys, tryst)?;
-
-                    smol::Timer::after(Duration::from_secs(1)).await;
-                }
-
-                assert!(waits.len() <= txs);
-                if waits.len() != txs {
-                    log::error!("failed to submit {} proofs", txs - waits.len());
-                }
-            }
-            if waits.len() > 0 {
-                let mut sub = worker
-                    .lock()
-                    .unwrap()
-                    .add_child("waiting for confirmation of proof");
-                sub.init(Some(waits.len()), None);
-                for to_wait in waits {
-                    opts.wallet.wait_transaction(to_wait).await?;
-                    sub.inc();
-                }
+            let (factor, factor_rounds) = bench::correction(&rounds, Some(my_difficulty), opts.threads, speed_source);
+            let my_speed = raw_speed / factor;
+            if factor_rounds > 0 {
+                log::info!("round time correction: x{:.3} (by {} recent rounds)", factor, factor_rounds);
             }
+            let approx_round = 2.0f64.powi(my_difficulty as _) / my_speed;
 
+            // the pool for display the expected return
             let erg_to_mel = client.snapshot().await?.get_pool(PoolKey::mel_and(Denom::Erg)).await?.expect("must have erg-mel pool");
-            let summary = opts.wallet.summary().await?;
-
-            // If we have any ERG (64), convert it all to MEL (6d).
-            let our_ergs = summary.detailed_balance.get("64").copied().unwrap_or_default();
-            if our_ergs > CoinValue(0) {
-                worker
-                    .lock()
-                    .unwrap()
-                    .message(MessageLevel::Info, format!("CONVERTING {} ERG!", our_ergs));
-                mint_state.convert_doscs(our_ergs).await?;
-            }
 
-            // check profit status, and/or quitting without incomes
-            mint_state.fee_handler.failsafe();
-
-            // skipping transfer profits if without payout address.
-            if let Some(payout) = opts.payout {
-                let our_mels: CoinValue = summary.total_micromel;
-                // If we have more than 1 MEL, transfer [half balance] to the backup wallet.
-                if our_mels > CoinValue::from_millions(1u8) {
-                    let to_transfer = our_mels / 2;
-                    worker.lock().unwrap().info(format!("balance of working-wallet: {} | profits have more than 1.0 MEL, transferring half to payout address...", our_mels));
-
-                    let to_send = opts
-                        .wallet
-                        .prepare_transaction(
-                            TxKind::Normal,
-                            vec![],
-                            vec![CoinData {
-                                covhash: payout,
-                                value: to_transfer,
-                                additional_data: vec![],
-                                denom: Denom::Mel,
-                            }],
-                            vec![],
-                            vec![],
-                            vec![],
-                        )
-                        .await?;
-                    let h = opts.wallet.send_tx(to_send).await?;
-                    worker.lock().unwrap().info( format!("sent {} MEL to payout wallet. tx hash: {}", to_transfer, h) );
-                    opts.wallet.wait_transaction(h).await?;
-                }
-            }
+            // the settlement of previous rounds (also the pending proofs loaded from disk) runs in background, the next rounds never wait for it
+            let queued = std::mem::take(&mut submit_proofs);
+            let waits = std::mem::take(&mut streamed);
+
+            // the left time of the mint window
+            let (my_difficulty, approx_round) = match window_left {
+                None => (my_difficulty, approx_round),
+                Some(left) if left.as_secs_f64() < approx_round && cli_opts.fixed_diff.is_none() => {
+                    // a smaller difficulty that finishes inside the window (but at most 1/8 of the work), otherwise it will be paused at the end of window.
+                    let fit = (left.as_secs_f64() * my_speed).log2().floor() as usize;
+                    let smaller = fit.max(my_difficulty.saturating_sub(3));
+                    if smaller < my_difficulty {
+                        log::info!("the mint window lefts {:?}, use a smaller difficulty {} (instead of {})", left, smaller, my_difficulty);
+                        (smaller, approx_round / 2f64.powi((my_difficulty - smaller) as i32))
+                    } else {
+                        (my_difficulty, approx_round)
+                    }
+                },
+                Some(left) => {
+                    if left.as_secs_f64() < approx_round {
+                        log::info!("the mint window lefts {:?}, this round ({:.0}s) will be paused at the end of window", left, approx_round);
+                    }
+                    (my_difficulty, approx_round)
+                },
+            };
 
             worker.lock().unwrap().message(
                 MessageLevel::Info,
                 format!(
                     "Selected difficulty {}: {} (approx. {:.3}s / tx)",
 
-                    if cli_opts.fixed_diff.is_none() { "[auto]" } else { "[fixed]" },
+                    if cli_opts.fixed_secs.is_some() { "[secs]" } else if cli_opts.fixed_diff.is_none() { "[auto]" } else { "[fixed]" },
                     my_difficulty,
                     approx_round,
                 ),
@@ -348,13 +383,83 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
             let fastest_speed = client.snapshot().await?.current_header().dosc_speed as f64 / 30.0;
             worker.lock().unwrap().info(format!("Max speed on chain: {:.2} kH/s", fastest_speed / 1000.0));
 
-            let seed_ttl = mint_state.seed_handler.set_expire(Duration::from_secs_f64(approx_round*2.0));
+            // the seed TTL follows the reward decay: a proof of too old seed cannot cover the DoscMint fee.
+            let round_blocks = (approx_round / 30.0).ceil() as u64;
+            let mint_fee = match mint_state.fee_handler.last_fee(TxKind::DoscMint) {
+                Some(fee) => Some(fee),
+                // no DoscMint sent yet (the first round): estimated by a tx of same size, so an unprofitable round is known before minting it
+                None => match mint_state.estimate_mint_fee(my_difficulty).await {
+                    Ok(fee) => {
+                        log::info!("estimated DoscMint fee of difficulty {}: {} MEL", my_difficulty, fee);
+                        Some(fee)
+                    },
+                    Err(e) => {
+                        log::warn!("cannot estimate the DoscMint fee: {:?}", e);
+                        None
+                    },
+                },
+            };
+            let break_even = match mint_fee {
+                Some(fee) => {
+                    let age = mint_state.break_even_age(my_difficulty, fee).await?;
+                    match age {
+                        None => {
+                            log::warn!("WARNING: the reward of difficulty {} cannot cover the DoscMint fee ({} MEL) even with a fresh seed!", my_difficulty, fee);
+                        },
+                        Some(age) if age < round_blocks => {
+                            log::warn!("WARNING: the estimated round time ({:.0}s, ~{} blocks) exceeds the break-even seed age ({} blocks), the reward of proofs will fall below the DoscMint fee ({} MEL)! consider a lower difficulty.", approx_round, round_blocks, age, fee);
+                        },
+                        _ => {}
+                    }
+                    // the user asked this round time, but it cannot be profitable: refuse it (instead of wasting the fees)
+                    if let Some(secs) = cli_opts.fixed_secs {
+                        if (! no_failsafe) && age.map(|age| age < round_blocks).unwrap_or(true) {
+                            panic_exit!(11, "the target round time --fixed-secs {} (difficulty {}) is unprofitable: the reward of proofs cannot cover the DoscMint fee ({} MEL). please use another target (or --no-failsafe if you know what you are doing)", secs, my_difficulty, fee);
+                        }
+                    }
+                    // unprofitable even with a fresh seed: no break-even age, the default TTL (a zero age would expire all seeds)
+                    age
+                },
+                None => None,
+            };
+            let seed_ttl = mint_state.seed_handler.set_expire(break_even, Duration::from_secs_f64(approx_round));
+
+            // a slower core gets a smaller difficulty, so all proofs finish around the same time
+            // (only if the threads are pinned, otherwise a thread is not tied to a core)
+            let difficulties: Vec<usize> = match &opts.cpus {
+                Some(cpus) => {
+                    let speeds: Vec<Option<f64>> = (0..threads).map(|idx| cpu_speeds.get(&cpus[idx % cpus.len()]).copied()).collect();
+                    let mut out = bench::thread_difficulties(my_difficulty, &speeds);
+
+                    // never lower than a profitable difficulty
+                    if let Some(fee) = mint_fee {
+                        let wanted = out.iter().copied().min().unwrap_or(my_difficulty);
+                        let mut lowest = my_difficulty;
+                        while lowest > wanted {
+                            match mint_state.break_even_age(lowest - 1, fee).await? {
+                                Some(age) if age >= round_blocks => { lowest -= 1; },
+                                _ => { break; }
+                            }
+                        }
+                        for d in out.iter_mut() {
+                            *d = (*d).max(lowest);
+                        }
+                    }
+                    if out.iter().any(|d| *d != my_difficulty) {
+                        log::info!("the difficulty of each thread (by the speed of its core): {:?}", out);
+                    }
+                    out
+                },
+                None => vec![my_difficulty; threads],
+            };
             worker.lock().unwrap().info(format!("Seed TTL: {} blocks ({}s)", seed_ttl, seed_ttl*30));
+            worker.lock().unwrap().info(format!("Seed Inventory: {}", seed_inventory()?));
             worker.lock().unwrap().info(format!("Minter Address: {}", summary.address));
             worker.lock().unwrap().info(format!("Minting Balance: {} MEL", summary.total_micromel));
 
-            // if requested, stopping before generate seed
+            // if requested, stopping before generate seed (after the finished proofs settled)
             if recv_stop.try_recv().is_ok() {
+                settle_after(settling.take(), &opts, &mint_state, &client, &worker, queued, waits).await?;
                 log::warn!("melminter process terminating");
                 std::process::exit(0);
             }
@@ -366,17 +471,33 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                     .unwrap()
                     .add_child("generating seed UTXOs for minting...");
                 sub.init(None, None);
-                mint_state.seed_handler.generate(client.clone(), threads, &mut mint_state.fee_handler).await?;
+                mint_state.seed_handler.generate(threads, &mut mint_state.fee_handler).await?;
             }
+            settling = Some(settle_after(settling.take(), &opts, &mint_state, &client, &worker, queued, waits));
+
+            // the seeds of this round are selected when minting starts, then the seeds of next round are generated while minting
+            let (send_started, recv_started) = smol::channel::bounded::<()>(1);
+            let mut next_seeds = mint_state.seed_handler.clone();
+            let mut next_fees = mint_state.fee_handler.clone();
+            let fees_before = next_fees.history.len();
 
             let batch_snapshot = client.snapshot().await?;
+            // the proofs submitted while minting (of all tries)
+            let round_streamed: Mutex<Streamed<FeeRecord>> = Mutex::new((vec![], vec![], vec![]));
             // repeat because wallet could be out of money
-            let batch: Vec<(CoinID, CoinDataHeight, Vec<u8>)> = repeat_fallible(|| {
+            let batch_fut = repeat_fallible(|| {
                 let mint_state = &mint_state;
+                let round_streamed = &round_streamed;
+                let send_started = send_started.clone();
+                // all threads running at the start (also when retrying)
+                mint_state.control.reset();
                 let subworkers = Arc::new(DashMap::new());
                 let worker = worker.clone();
 
-                let total = 100 * (1usize << ( my_difficulty.saturating_sub(10) ));
+                // the progress total of each thread
+                let totals: Vec<usize> = difficulties.iter().map(|d| 100 * (1usize << ( d.saturating_sub(10) ))).collect();
+                let total_sum: usize = totals.iter().sum();
+                let difficulties = difficulties.clone();
                 let snapshot = batch_snapshot.clone();
 
                 // background task that tallies speeds
@@ -385,13 +506,14 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                     let worker = worker.clone();
                     let snapshot = snapshot.clone();
                     let wallet = opts.wallet.clone();
+                    let control = mint_state.control.clone();
                     Arc::new(smol::spawn(async move {
                         let mut previous: HashMap<usize, usize> = HashMap::new();
                         let mut _space;
                         let mut delta_sum = 0;
                         let start = Instant::now();
 
-                        let total_sum = (total * threads) as f64;
+                        let total_sum = total_sum as f64;
 
                         let disconnect_timeout = Duration::from_secs(600); // ten minutes
                         let mut disconnect_started: Option<Instant> = None;
@@ -428,7 +550,7 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                                         log::error!("Failed to get wallet summary: {:?}", e);
                                         log::warn!("Cannot connect to the melwalletd daemon! Melminter will try again until connected...");
                                         log::info!("the mint progress will still continue, BUT PLEASE NOTE: your mint incomes will be ZERO if the daemon connection cannot recovered.");
-                                        log::info!("For save your CPU computing resources, the program will exit if disconnected a long time (timeout is {:?})", disconnect_timeout);
+                                        log::info!("For save your CPU computing resources, the current round will be cancelled if disconnected a long time (timeout is {:?})", disconnect_timeout);
                                         disconnect_started = Some(Instant::now());
                                     }
 
@@ -442,8 +564,9 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                                     // this check is mainly to prevent un-necessary CPU-time waste.
                                     if disconnect_started.unwrap().elapsed() > disconnect_timeout {
                                         log::error!("the daemon connection recovery failed because timeout-ed! ({:?})", disconnect_timeout);
-
-                                        panic_exit!(90, "because still does not recovery the daemon connection, so exit minting to avoid waste the CPU computing resources.");
+                                        log::warn!("because still does not recovery the daemon connection, so cancel the current round to avoid waste the CPU computing resources. (will retry after connected)");
+                                        control.cancel();
+                                        break;
                                     }
 
                                     // to retry...
@@ -453,13 +576,21 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                             let mel_balance = summary.detailed_balance.get("6d").unwrap();
 
                             let mut new = worker.lock().unwrap().add_child(
-                                format!( "current progress: {:.2} % (lefts? {:.1}s) | fee reserve: {} MEL | expected daily return: {:.3} DOSC ≈ {:.3} ERG ≈ {:.3} MEL",
+                                format!( "{}current progress: {:.2} % (lefts? {:.1}s) | throttled: {:.0}s | fee reserve: {} MEL | expected daily return: {:.3} DOSC ≈ {:.3} ERG ≈ {:.3} MEL",
+                                         if control.is_paused() { "[paused] " } else { "" },
                                          (curr_sum/total_sum) * 100.0,
                                          {
                                              let used = (curr_sum/total_sum) * approx_round;
                                              let left = approx_round - used;
-                                             left
+                                             // only the active threads are running (the expected return is already measured by the real speed)
+                                             let active = control.active().min(threads);
+                                             if control.is_paused() || active == 0 {
+                                                 f64::INFINITY
+                                             } else {
+                                                 left * (threads as f64) / (active as f64)
+                                             }
                                          },
+                                         control.throttled_time().as_secs_f64(),
                                          mel_balance,
                                          dosc_per_day, erg_per_day, mel_per_day
                                 )
@@ -470,66 +601,187 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
                     }))
                 };
 
+                // background task that pauses/resumes the mint threads by the schedule
+                let schedule_task: Option<Task<()>> = cli_opts.schedule.clone().map(|sched| {
+                    let control = mint_state.control.clone();
+                    smol::spawn(async move {
+                        loop {
+                            let inside = sched.contains(schedule::now().0);
+                            if inside && control.is_paused() {
+                                log::info!("the mint window started, resuming mint threads");
+                                control.resume();
+                            } else if (!inside) && (!control.is_paused()) {
+                                log::info!("the mint window ended, pausing mint threads until next window");
+                                control.pause();
+                            }
+                            smol::Timer::after(Duration::from_secs(20)).await;
+                        }
+                    })
+                });
+
+                // background task that throttles the mint threads by load and temperature
+                let governor = Governor { max_load: cli_opts.max_load, max_temp: cli_opts.max_temp };
+                let governor_task: Option<Task<()>> = if governor.enabled() {
+                    let control = mint_state.control.clone();
+                    Some(smol::spawn(async move {
+                        let mut active = threads;
+                        loop {
+                            smol::Timer::after(Duration::from_secs(10)).await;
+                            let (load, temp) = (governor::read_loadavg(), governor::read_temperature());
+                            let next = governor.decide(load, temp, active, threads);
+                            if next != active {
+                                log::info!("(governor) load {:?}, temperature {:?} °C: {} of {} mint threads active", load, temp, next, threads);
+                                active = next;
+                                control.set_active(if active >= threads { usize::MAX } else { active });
+                            }
+                        }
+                    }))
+                } else {
+                    None
+                };
+
+                let client = client.clone();
                 async move {
                     let started = Instant::now();
 
-                    let res = mint_state.mint_batch(
-                        my_difficulty,
-                        move |a, b| {
-                            let mut subworker = subworkers.entry(a).or_insert_with(|| {
-                                let mut child = worker
-                                    .lock()
-                                    .unwrap()
-                                    .add_child(format!("subworker {}", a));
-                                child.init(
-                                    Some(total),
-                                    Some(prodash::unit::dynamic_and_mode(
-                                        "kH",
-                                        Mode::with_throughput(),
-                                    )),
-                                );
-                                child
-                            });
-                            subworker.set(((total as f64) * b) as usize);
-                        },
-                        threads,
-                    ).await?;
+                    // each proof is stored and submitted as soon as its thread finished, while the other threads continue
+                    let (send_proof, recv_proof) = smol::channel::unbounded::<TrySendProof>();
+                    let submitter = stream_proofs(recv_proof, |trys, mut tryst| {
+                        let client = &client;
+                        async move {
+                            let result = try_submit(mint_state, client, &trys, &mut tryst).await
+                                .map(|sent| sent.map(|(txhash, reward_ergs, fee_rec)| {
+                                    log::info!("(proof sent) thread {} minted {} ERG, tx hash: {}", trys.thread, CoinValue(reward_ergs), txhash);
+                                    (txhash, fee_rec)
+                                }));
+                            (tryst, result)
+                        }
+                    });
+
+                    let mint = async {
+                        let res = mint_state.mint_batch(
+                            &difficulties,
+                            move || {
+                                let _ = send_started.try_send(());
+                            },
+                            move |a, b| {
+                                let total = totals[a];
+                                let mut subworker = subworkers.entry(a).or_insert_with(|| {
+                                    let mut child = worker
+                                        .lock()
+                                        .unwrap()
+                                        .add_child(format!("subworker {}", a));
+                                    child.init(
+                                        Some(total),
+                                        Some(prodash::unit::dynamic_and_mode(
+                                            "kH",
+                                            Mode::with_throughput(),
+                                        )),
+                                    );
+                                    child
+                                });
+                                subworker.set(((total as f64) * b) as usize);
+                            },
+                            move |trys| {
+                                let _ = send_proof.try_send(trys.clone());
+                            },
+                            threads,
+                        ).await;
+                        // the time of minting, without the submission of the last proof
+                        (res, started.elapsed().as_secs_f64(), mint_state.control.throttled_time().as_secs_f64())
+                    };
+                    let ((res, ended, throttled), (sent, unsent, fee_recs)) = smol::future::zip(mint, submitter).await;
+                    // the proofs already submitted are kept, even if the minting failed (and retried)
+                    {
+                        let mut out = round_streamed.lock().unwrap();
+                        out.0.extend(sent);
+                        out.1.extend(unsent);
+                        out.2.extend(fee_recs);
+                    }
+                    let res = res?;
+                    if mint_state.control.is_cancelled() {
+                        log::warn!("the round cancelled, {} of {} proofs finished", res.len(), threads);
+                    }
 
-                    let ended = started.elapsed().as_secs_f64();
-                    let kh = total * threads;
-                    println!("Proof Completed {} kH (total {:.3} threads) in time {:.3}s | Average Speed: {:.3}kH/s | Offset: (approx){:.3}s - (real){:.3}s = {:.3}s",
-                        kh, threads, ended,
+                    let kh = total_sum;
+                    println!("Proof Completed {} kH (total {:.3} threads) in time {:.3}s (throttled {:.3}s) | Average Speed: {:.3}kH/s | Offset: (approx){:.3}s - (real){:.3}s = {:.3}s",
+                        kh, threads, ended, throttled,
                         (kh as f64) / ended,
 
-                        // calculating deviation for improve the accuracy of predicted time spent...
+                        // calculating deviation for improve the accuracy of predicted time spent... (the throttled time is not a deviation)
                         approx_round,
-                        ended,
-                        approx_round - ended,
+                        ended - throttled,
+                        approx_round - (ended - throttled),
                     );
+                    // learn from the offset (only a complete round of the same difficulty: the prediction is by one difficulty for all threads)
+                    if res.len() == threads && difficulties.iter().all(|d| *d == my_difficulty) {
+                        let rec = db::RoundTiming {
+                            time: SystemTime::now(),
+                            difficulty: my_difficulty,
+                            threads,
+                            predicted: 2.0f64.powi(my_difficulty as _) / raw_speed,
+                            actual: ended - throttled,
+                            source: speed_source,
+                        };
+                        if let Err(e) = db::add_round(rec) {
+                            log::warn!("cannot store the round timing: {:?}", e);
+                        }
+                    }
 
                     std::mem::drop(speed_task);
-                    Ok::<_, surf::Error>(res)
+                    std::mem::drop(schedule_task);
+                    std::mem::drop(governor_task);
+                    mint_state.control.reset();
+                    Ok::<_, surf::Error>((res, throttled))
+                }
+            });
+            let next_fut = async {
+                if recv_started.recv().await.is_err() || mint_state.control.is_stopped() {
+                    return;
+                }
+                if let Err(e) = next_seeds.generate(threads, &mut next_fees).await {
+                    log::warn!("cannot generate the seeds of next round while minting (will retry before it): {:?}", e);
                 }
-            }).await;
+            };
+            // mint this round, while the seeds of next round generating
+            let ((batch, throttled), ()) = smol::future::zip(batch_fut, next_fut).await;
+            mint_state.seed_handler = next_seeds;
+            mint_state.fee_handler.record(next_fees.history.drain(fees_before..));
+            let (sent, unsent, fee_recs) = round_streamed.into_inner().unwrap();
 
             worker.lock().unwrap().message(
                 MessageLevel::Info,
                 format!("built batch of {} future proofs", batch.len()),
             );
 
-            for (coin, data, proof) in batch {
-                let trys = TrySendProof { coin, data, proof };
-                let tryst = TrySendProofState {
-                    fails: 0u8,
-                    created: SystemTime::now(),
-                    sent: false,
-                    failed: false,
-                    errors: vec![],
-                };
-                
-                map.set(trys.clone(), tryst.clone())?;
-                submit_proofs.push_back((trys, tryst));
+            // the real speed of each thread (a throttled round does not tell the speed)
+            if throttled == 0.0 && ! batch.is_empty() {
+                let speed = batch.iter()
+                    .map(|trys| 2.0f64.powi(trys.difficulty as _) / trys.elapsed.as_secs_f64())
+                    .sum::<f64>() / batch.len() as f64;
+                live_speed = Some(match live_speed {
+                    None => speed,
+                    Some(prev) => (prev + speed) / 2.0,
+                });
+                log::info!("measured speed of this round: {:.2} kH/s per thread", speed / 1000.0);
+
+                if let Some(cpus) = &opts.cpus {
+                    for trys in &batch {
+                        let cpu = cpus[trys.thread % cpus.len()];
+                        let speed = 2.0f64.powi(trys.difficulty as _) / trys.elapsed.as_secs_f64();
+                        let s = cpu_speeds.entry(cpu).or_insert(speed);
+                        *s = (*s + speed) / 2.0;
+                    }
+                }
             }
+
+            // the proofs are already stored (and submitted if possible) while minting
+            mint_state.fee_handler.record(fee_recs);
+            streamed.extend(sent);
+            submit_proofs.extend(unsent);
+
+            // check profit status, and/or quitting without incomes
+            mint_state.fee_handler.failsafe();
 //            dict_proofs.flush()?;
         }
 
@@ -539,7 +791,7 @@ async fn main_async(opts: WorkerConfig, recv_stop: Receiver<()>) -> surf::Result
     Ok(())
 }
 
-// Computes difficulty
+// Computes difficulty (a short benchmark of single thread, only used if no calibration stored)
 async fn compute_speed() -> f64 {
     for difficulty in 1.. {
         let start = Instant::now();
@@ -553,6 +805,289 @@ async fn compute_speed() -> f64 {
     unreachable!()
 }
 
+// how long to wait for the confirmation of proofs submitted before restart (in total, all of them are checked at once)
+const RECHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+// The status of a proof submitted before restart: confirmed if its DoscMint tx confirmed before the deadline,
+// otherwise None (maybe still in mempool, or the status cannot be got now), so it stays submitted and will be re-checked later.
+async fn recheck_submitted(wallet: &WalletClient, txhash: TxHash, deadline: Instant) -> Option<ProofStatus> {
+    let wait = deadline.saturating_duration_since(Instant::now());
+    let confirmed = smol::future::or(
+        async { Some(wallet.wait_transaction(txhash).await) },
+        async {
+            smol::Timer::after(wait).await;
+            None
+        },
+    ).await;
+
+    match confirmed {
+        Some(Ok(height)) => Some(ProofStatus::Confirmed(height)),
+        Some(Err(e)) => {
+            log::warn!("cannot get the status of tx {} after restart: {:?}", txhash, e);
+            None
+        },
+        None => None,
+    }
+}
+
+// The settlement of finished rounds: submits the queued proofs (retry each at most 3 times), waits for the confirmation of submitted proofs,
+// converts the ERG to MEL and transfers the profits to payout address. returns the fee records.
+// (it only needs a shared MintState, so it runs while the next round minting)
+async fn settle(
+    opts: &WorkerConfig,
+    mint_state: &MintState,
+    client: &ValClient,
+    worker: &Arc<Mutex<Item>>,
+    mut submit_proofs: VecDeque<(TrySendProof, TrySendProofState)>,
+    mut waits: Vec<(TxHash, TrySendProof, TrySendProofState)>,
+) -> surf::Result<Vec<FeeRecord>> {
+    let mut fee_recs = vec![];
+
+    // Time to submit the proofs (already stored in disk). for every proof in the queue, we attempt to submit it. If the submission fails, we move on, because there might be some weird race condition with melwalletd going on.
+    if submit_proofs.len() > 0 {
+        let txs = submit_proofs.len();
+        let mut sent = 0;
+
+        let mut sub = worker.lock().unwrap().add_child("submitting proof");
+        sub.init(Some(txs), None);
+
+        let max_retry: u8 = 3;
+        while submit_proofs.len() > 0 {
+            let (trys, mut tryst) = submit_proofs.pop_front().unwrap();
+            let (coin, data) = (trys.coin, &trys.data);
+
+            match try_submit(mint_state, client, &trys, &mut tryst).await {
+                Err(err) => {
+                    log::warn!("FAILED a proof submission for some reason: {:?}", err);
+
+                    if tryst.fails <= max_retry {
+                        submit_proofs.push_back( ( trys.clone(), tryst.clone() ) );
+                    } else {
+                        log::error!("Dropping proof {:?} from submit queue, because reach max retry limit", (coin, data));
+                        tryst.status = ProofStatus::Failed(format!("reach max retry limit ({})", max_retry));
+                        tryst.updated = SystemTime::now();
+                    }
+                },
+                Ok(None) => {
+                    db::write_proof(&trys, &tryst)?;
+                    continue;
+                },
+                Ok(Some((res, reward_ergs, fee_rec))) => {
+                    fee_recs.push(fee_rec);
+                    waits.push((res, trys.clone(), tryst.clone()));
+                    sent += 1;
+
+                    sub.inc();
+                    sub.info(format!("(proof sent) minted {} ERG", CoinValue(reward_ergs)));
+                }
+            }
+            db::write_proof(&trys, &tryst)?;
+
+            smol::Timer::after(Duration::from_secs(1)).await;
+        }
+
+        if sent != txs {
+            log::error!("failed to submit {} proofs", txs - sent);
+        }
+    }
+    if waits.len() > 0 {
+        let mut sub = worker
+            .lock()
+            .unwrap()
+            .add_child("waiting for confirmation of proof");
+        sub.init(Some(waits.len()), None);
+        for (to_wait, trys, mut tryst) in waits {
+            let height = opts.wallet.wait_transaction(to_wait).await?;
+
+            tryst.status = ProofStatus::Confirmed(height);
+            tryst.updated = SystemTime::now();
+            db::write_proof(&trys, &tryst)?;
+            sub.inc();
+        }
+    }
+
+    let summary = opts.wallet.summary().await?;
+
+    // If we have any ERG (64), convert it all to MEL (6d).
+    let our_ergs = summary.detailed_balance.get("64").copied().unwrap_or_default();
+    if our_ergs > CoinValue(0) {
+        worker
+            .lock()
+            .unwrap()
+            .message(MessageLevel::Info, format!("CONVERTING {} ERG!", our_ergs));
+        fee_recs.push(mint_state.convert_doscs(our_ergs).await?);
+    }
+
+    // skipping transfer profits if without payout address.
+    if let Some(payout) = opts.payout {
+        let our_mels: CoinValue = opts.wallet.summary().await?.total_micromel;
+        // If we have more than 1 MEL, transfer [half balance] to the backup wallet.
+        if our_mels > CoinValue::from_millions(1u8) {
+            let to_transfer = our_mels / 2;
+            worker.lock().unwrap().info(format!("balance of working-wallet: {} | profits have more than 1.0 MEL, transferring half to payout address...", our_mels));
+
+            let h = mint_state.send_payout(payout, to_transfer).await?;
+            worker.lock().unwrap().info( format!("sent {} MEL to payout wallet. tx hash: {}", to_transfer, h) );
+            opts.wallet.wait_transaction(h).await?;
+        }
+    }
+
+    Ok(fee_recs)
+}
+
+// the proofs sent (with tx hash), the proofs not sent (retried after the round), and the results of the sent proofs
+type Streamed<R> = (Vec<(TxHash, TrySendProof, TrySendProofState)>, Vec<(TrySendProof, TrySendProofState)>, Vec<R>);
+
+/// Stores and submits each proof as soon as its thread finished, while the other threads continue.
+/// a proof is stored (as pending) before `submit`, so it is never lost even if the submission fails; `submit` returns the tx hash and a result of a sent proof, or None if not sent (quarantined).
+pub(crate) async fn stream_proofs<R, Fut>(
+    recv_proof: Receiver<TrySendProof>,
+    submit: impl Fn(TrySendProof, TrySendProofState) -> Fut,
+) -> Streamed<R>
+where
+    Fut: Future<Output = (TrySendProofState, surf::Result<Option<(TxHash, R)>>)>,
+{
+    let (mut sent, mut unsent, mut results) = (vec![], vec![], vec![]);
+    while let Ok(trys) = recv_proof.recv().await {
+        let now = SystemTime::now();
+        let tryst = TrySendProofState {
+            fails: 0u8,
+            created: now,
+            updated: now,
+            status: ProofStatus::Pending,
+            errors: vec![],
+        };
+        // stored before submit, so it will never lost
+        if let Err(e) = db::write_proof(&trys, &tryst) {
+            log::error!("cannot store the proof of thread {}: {:?}", trys.thread, e);
+        }
+
+        let (tryst, result) = submit(trys.clone(), tryst).await;
+        match result {
+            Ok(Some((txhash, r))) => {
+                results.push(r);
+                sent.push((txhash, trys.clone(), tryst.clone()));
+            },
+            Ok(None) => {},
+            Err(err) => {
+                log::warn!("FAILED a proof submission of thread {}, it will be retried after the round: {:?}", trys.thread, err);
+                unsent.push((trys.clone(), tryst.clone()));
+            },
+        }
+        if let Err(e) = db::write_proof(&trys, &tryst) {
+            log::error!("cannot store the proof of thread {}: {:?}", trys.thread, e);
+        }
+    }
+    (sent, unsent, results)
+}
+
+// Starts the settlement in background, after the previous settlement (if any) finished: only one settlement at a time.
+// returns the fee records of both.
+fn settle_after(
+    prev: Option<Task<surf::Result<Vec<FeeRecord>>>>,
+    opts: &WorkerConfig,
+    mint_state: &MintState,
+    client: &ValClient,
+    worker: &Arc<Mutex<Item>>,
+    submit_proofs: VecDeque<(TrySendProof, TrySendProofState)>,
+    waits: Vec<(TxHash, TrySendProof, TrySendProofState)>,
+) -> Task<surf::Result<Vec<FeeRecord>>> {
+    let (opts, mint_state, client, worker) = (opts.clone(), mint_state.clone(), client.clone(), worker.clone());
+    smol::spawn(async move {
+        let mut fee_recs = match prev {
+            Some(task) => task.await?,
+            None => vec![],
+        };
+        fee_recs.extend(settle(&opts, &mint_state, &client, &worker, submit_proofs, waits).await?);
+        Ok(fee_recs)
+    })
+}
+
+// Verifies and submits a proof, the state of it is updated (but not stored).
+// returns the tx hash, the reward (ERG) and the fee record if sent; None if quarantined (invalid proof); an error if failed (the fails counted).
+async fn try_submit(
+    mint_state: &MintState,
+    client: &ValClient,
+    trys: &TrySendProof,
+    tryst: &mut TrySendProofState,
+) -> surf::Result<Option<(TxHash, u128, FeeRecord)>> {
+    let result = async {
+        let (coin, data, proof, difficulty) = (trys.coin, &trys.data, &trys.proof, trys.difficulty);
+
+        let snap = client.snapshot().await?;
+        let header = snap.current_header();
+        let reward_ergs = proof_reward(&header, difficulty, header.height.0.saturating_sub(data.height.0));
+
+        // make sure the proof is valid before submit it, otherwise it just waste the fee of DoscMint tx.
+        if ! mint_state.verify_proof(trys).await? {
+            log::error!("Quarantine an invalid proof {:?} (difficulty {}), it will never be submitted!", (coin, data), difficulty);
+            db::write_log(
+                &format!("{:?}", coin),
+                db::WhatLog::Exception,
+                "invalid proof",
+                &format!("proof of seed {:?} cannot pass local verification with difficulty {}, quarantined", (coin, data), difficulty),
+                bincode::serialize(trys)?,
+            )?;
+            return Ok(None);
+        }
+
+        let (txhash, fee_rec) = mint_state.send_mint_transaction(coin, difficulty, proof.clone(), reward_ergs.into()).await?;
+        Ok::<_, surf::Error>(Some((txhash, reward_ergs, fee_rec)))
+    }.await;
+
+    match result {
+        Ok(Some((txhash, reward_ergs, fee_rec))) => {
+            tryst.status = ProofStatus::Submitted(txhash);
+            tryst.updated = SystemTime::now();
+            Ok(Some((txhash, reward_ergs, fee_rec)))
+        },
+        Ok(None) => {
+            tryst.status = ProofStatus::Quarantined(format!("local verification failed with difficulty {}", trys.difficulty));
+            tryst.updated = SystemTime::now();
+            Ok(None)
+        },
+        Err(err) => {
+            tryst.fails += 1;
+            tryst.errors.push( format!("{:?} | {:?}", SystemTime::now(), err) );
+            Err(err)
+        },
+    }
+}
+
+// count the seed records by state, for status output
+fn seed_inventory() -> anyhow::Result<String> {
+    let (mut fresh, mut in_use, mut proved, mut expired, mut swept, mut split) = (0, 0, 0, 0, 0, 0);
+    for (_, rec) in db::load_seeds()? {
+        match rec.state {
+            SeedState::Fresh => fresh += 1,
+            SeedState::InUse => in_use += 1,
+            SeedState::Proved => proved += 1,
+            SeedState::Expired => expired += 1,
+            SeedState::Swept(_) => swept += 1,
+            SeedState::Split(_) => split += 1,
+        }
+    }
+    Ok(format!("{} fresh, {} in-use, {} proved, {} expired, {} swept, {} split", fresh, in_use, proved, expired, swept, split))
+}
+
+// Removes the records of finished (confirmed/failed/exported) proofs that have not been updated within the retention, returns how many removed.
+// pending, submitted (not confirmed yet) and quarantined proofs are always kept.
+fn prune_proofs(map: &mut db::Map, retention: Duration) -> anyhow::Result<usize> {
+    let mut pruned = 0;
+    for (trys, tryst) in db::load_proofs(map)? {
+        match tryst.status {
+            ProofStatus::Confirmed(_) | ProofStatus::Failed(_) | ProofStatus::Exported(_) => {},
+            _ => { continue; }
+        }
+
+        let age = tryst.updated.elapsed().unwrap_or_default();
+        if age > retention && map.remove(&trys)? {
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
 async fn get_valclient(net: NetID, connect: SocketAddr) -> anyhow::Result<ValClient> {
     let client = themelio_nodeprot::ValClient::new(net, connect);
 